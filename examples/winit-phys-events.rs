@@ -35,11 +35,11 @@ const SLOW_PRESET: (Duration, Duration) =
 
 
 struct App {
-  keys: Keys<Key>,
+  keys: Keys<Key, Instant, DeviceId>,
 }
 
 impl App {
-  fn new(keys: Keys<Key>) -> Self {
+  fn new(keys: Keys<Key, Instant, DeviceId>) -> Self {
     Self { keys }
   }
 }
@@ -59,12 +59,12 @@ impl ApplicationHandler for App {
   fn device_event(
     &mut self,
     _event_loop: &ActiveEventLoop,
-    _device_id: DeviceId,
+    device_id: DeviceId,
     event: DeviceEvent,
   ) {
-    // Handle all raw key events and ignore everything else. These
-    // events do not include any auto-repeats, as this is typically a
-    // software construct.
+    // Handle all raw key events as well as device removals and ignore
+    // everything else. Raw key events do not include any auto-repeats,
+    // as this is typically a software construct.
     if let DeviceEvent::Key(RawKeyEvent {
       physical_key: PhysicalKey::Code(key),
       state,
@@ -81,9 +81,13 @@ impl ApplicationHandler for App {
 
       let now = Instant::now();
       match state {
-        ElementState::Pressed => self.keys.on_key_press(now, key),
-        ElementState::Released => self.keys.on_key_release(now, key),
+        ElementState::Pressed => self.keys.on_device_key_press(now, device_id, key),
+        ElementState::Released => self.keys.on_device_key_release(now, device_id, key),
       }
+    } else if let DeviceEvent::Removed = event {
+      // Make sure that keys held on a device that just got unplugged
+      // do not stay pressed forever.
+      let () = self.keys.release_device(Instant::now(), &device_id);
     }
   }
 
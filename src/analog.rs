@@ -0,0 +1,515 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for driving key presses from analog inputs such as
+//! gamepad sticks and triggers.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::f32::consts::FRAC_PI_2;
+use std::f32::consts::FRAC_PI_4;
+use std::f32::consts::TAU;
+use std::hash::Hash;
+use std::ops::BitOrAssign;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Config;
+use crate::KeyRepeat;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// Thresholds for translating an axis value into key presses and
+/// releases.
+///
+/// Using a release threshold below the press threshold prevents a
+/// value hovering around a single threshold from causing a flurry of
+/// presses and releases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hysteresis {
+  /// The value at or above which a key is pressed.
+  press: f32,
+  /// The value below which a pressed key is released.
+  release: f32,
+}
+
+impl Hysteresis {
+  /// Create a new [`Hysteresis`] object with the provided `press` and
+  /// `release` thresholds.
+  ///
+  /// A `release` threshold above `press` is clamped to the latter, as
+  /// keys would otherwise get released right after being pressed.
+  pub fn new(press: f32, release: f32) -> Self {
+    Self {
+      press,
+      release: release.min(press),
+    }
+  }
+
+  /// Retrieve the value at or above which a key is pressed.
+  #[inline]
+  pub fn press(&self) -> f32 {
+    self.press
+  }
+
+  /// Retrieve the value below which a pressed key is released.
+  #[inline]
+  pub fn release(&self) -> f32 {
+    self.release
+  }
+}
+
+
+/// The default angular hysteresis of a [`Stick`], in radians.
+const DEFAULT_ANGULAR_HYSTERESIS: f32 = FRAC_PI_4 / 8.0;
+
+
+/// The number of directions a [`Stick`] distinguishes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Directions {
+  /// Only the four cardinal directions are reported.
+  #[default]
+  Four,
+  /// Diagonals are reported as well, by pressing both adjacent keys.
+  Eight,
+}
+
+
+/// A two dimensional stick, mapping its directions to keys.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Stick<K> {
+  /// The key for upward deflection.
+  pub up: K,
+  /// The key for downward deflection.
+  pub down: K,
+  /// The key for leftward deflection.
+  pub left: K,
+  /// The key for rightward deflection.
+  pub right: K,
+  /// The radial dead zone, in which any deflection is ignored.
+  ///
+  /// Deflection outside of the dead zone is rescaled to cover the full
+  /// range again.
+  pub dead_zone: f32,
+  /// The directions distinguished.
+  pub directions: Directions,
+  /// The angle, in radians, by which the stick has to move past the
+  /// boundary of the sector it is currently deflected into before
+  /// another sector is selected.
+  ///
+  /// Without it, a stick resting close to a sector boundary would
+  /// rapidly switch between the adjacent directions.
+  pub angular_hysteresis: f32,
+}
+
+impl<K> Stick<K> {
+  /// Create a new [`Stick`] object mapping its directions to the
+  /// provided keys, without a dead zone, distinguishing four
+  /// directions, and using a small angular hysteresis.
+  pub fn new(up: K, down: K, left: K, right: K) -> Self {
+    Self {
+      up,
+      down,
+      left,
+      right,
+      dead_zone: 0.0,
+      directions: Directions::default(),
+      angular_hysteresis: DEFAULT_ANGULAR_HYSTERESIS,
+    }
+  }
+
+  /// Set the radial dead zone.
+  pub fn with_dead_zone(mut self, dead_zone: f32) -> Self {
+    self.dead_zone = dead_zone;
+    self
+  }
+
+  /// Set the directions to distinguish.
+  pub fn with_directions(mut self, directions: Directions) -> Self {
+    self.directions = directions;
+    self
+  }
+
+  /// Set the angular hysteresis, in radians.
+  pub fn with_angular_hysteresis(mut self, angular_hysteresis: f32) -> Self {
+    self.angular_hysteresis = angular_hysteresis;
+    self
+  }
+
+  /// Retrieve the angular width of a single sector and the number of
+  /// sectors.
+  fn sectors(&self) -> (f32, f32) {
+    match self.directions {
+      Directions::Four => (FRAC_PI_2, 4.0),
+      Directions::Eight => (FRAC_PI_4, 8.0),
+    }
+  }
+
+  /// Infer the sector the stick is deflected into from the directions
+  /// currently `active`, in the order up, down, left, right.
+  fn sector(&self, active: [bool; 4]) -> Option<f32> {
+    let sector = match (self.directions, active) {
+      (_, [false, false, false, true]) => 0.0,
+      (_, [true, false, false, false]) => 2.0,
+      (_, [false, false, true, false]) => 4.0,
+      (_, [false, true, false, false]) => 6.0,
+      (Directions::Eight, [true, false, false, true]) => 1.0,
+      (Directions::Eight, [true, false, true, false]) => 3.0,
+      (Directions::Eight, [false, true, true, false]) => 5.0,
+      (Directions::Eight, [false, true, false, true]) => 7.0,
+      _ => return None,
+    };
+
+    match self.directions {
+      Directions::Four => Some(sector / 2.0),
+      Directions::Eight => Some(sector),
+    }
+  }
+
+  /// Translate a stick position into per-key values, in the order
+  /// up, down, left, right, given the directions currently `active`.
+  fn values(&self, x: f32, y: f32, active: [bool; 4]) -> [f32; 4] {
+    let magnitude = x.hypot(y).min(1.0);
+    if magnitude.is_nan() || magnitude <= self.dead_zone {
+      return [0.0; 4]
+    }
+
+    let value = ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+    let angle = y.atan2(x);
+    let (width, count) = self.sectors();
+    let sector = self
+      .sector(active)
+      .filter(|current| {
+        // Stick to the current sector for as long as the angle stays
+        // within the dead band around its boundaries.
+        let distance = (angle - current * width).rem_euclid(TAU);
+        distance.min(TAU - distance) <= width / 2.0 + self.angular_hysteresis
+      })
+      .unwrap_or_else(|| (angle / width).round().rem_euclid(count));
+
+    let [up, down, left, right] = match self.directions {
+      Directions::Four => [sector == 1.0, sector == 3.0, sector == 2.0, sector == 0.0],
+      Directions::Eight => [
+        (1.0..=3.0).contains(&sector),
+        (5.0..=7.0).contains(&sector),
+        (3.0..=5.0).contains(&sector),
+        sector == 7.0 || sector <= 1.0,
+      ],
+    };
+    [up, down, left, right].map(|active| if active { value } else { 0.0 })
+  }
+}
+
+
+/// The state of a single axis.
+#[derive(Clone, Copy, Debug)]
+struct Axis {
+  /// The thresholds in use.
+  thresholds: Hysteresis,
+  /// The most recently reported value.
+  value: f32,
+}
+
+
+/// An adapter translating analog axis values into key presses and
+/// releases on [`Keys`].
+///
+/// Each key is driven by an axis value in the range `[0, 1]`, which is
+/// compared against [`Hysteresis`] thresholds. While a key is held, its
+/// repeat interval is scaled with the deflection: at the press
+/// threshold repeats happen at [`Config::interval`], and at full
+/// deflection at the configured minimum interval.
+#[derive(Clone, Debug)]
+pub struct Analog<K, I = Instant> {
+  /// The thresholds used for keys without explicitly set ones.
+  thresholds: Hysteresis,
+  /// The repeat interval at full deflection.
+  min_interval: Duration,
+  /// The state of all axes reported so far.
+  axes: HashMap<K, Axis>,
+  /// The underlying key state.
+  keys: Keys<K, I>,
+}
+
+impl<K, I> Analog<K, I>
+where
+  K: Clone + Eq + Hash,
+  I: Timestamp,
+{
+  /// Create a new [`Analog`] object, using `thresholds` for all keys
+  /// and scaling the repeat interval between [`Config::interval`] and
+  /// `min_interval`.
+  pub fn new(thresholds: Hysteresis, min_interval: Duration, config: Config) -> Self {
+    Self {
+      thresholds,
+      min_interval,
+      axes: HashMap::new(),
+      keys: Keys::with_config(config),
+    }
+  }
+
+  /// Set the thresholds to use for `key`.
+  pub fn set_thresholds(&mut self, key: K, thresholds: Hysteresis) {
+    let default = self.thresholds;
+    self
+      .axes
+      .entry(key)
+      .or_insert(Axis {
+        thresholds: default,
+        value: 0.0,
+      })
+      .thresholds = thresholds;
+  }
+
+  /// Retrieve the underlying [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<K, I> {
+    &self.keys
+  }
+
+  /// Retrieve the most recently reported value of the axis driving
+  /// `key`.
+  pub fn value<Q>(&self, key: &Q) -> f32
+  where
+    K: Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+  {
+    self
+      .axes
+      .get(key)
+      .map(|axis| axis.value)
+      .unwrap_or_default()
+  }
+
+  /// This method is to be invoked on every value reported for the axis
+  /// driving `key`.
+  ///
+  /// `value` is expected to be in the range `[0, 1]` and clamped
+  /// otherwise.
+  pub fn on_axis(&mut self, now: I, key: K, value: f32) {
+    let value = if value.is_nan() {
+      0.0
+    } else {
+      value.clamp(0.0, 1.0)
+    };
+    let default = self.thresholds;
+    let axis = self.axes.entry(key.clone()).or_insert(Axis {
+      thresholds: default,
+      value: 0.0,
+    });
+    axis.value = value;
+
+    let thresholds = axis.thresholds;
+    let pressed = self.keys.is_pressed(&key);
+    if !pressed && value >= thresholds.press {
+      self.keys.on_key_press(now, key)
+    } else if pressed && value < thresholds.release {
+      self.keys.on_key_release(now, key)
+    }
+  }
+
+  /// This method is to be invoked on every position reported for the
+  /// two dimensional `stick`.
+  ///
+  /// `x` and `y` are expected to be in the range `[-1, 1]`, with
+  /// positive values meaning right and up, respectively.
+  pub fn on_stick(&mut self, now: I, stick: &Stick<K>, x: f32, y: f32) {
+    let keys = [&stick.up, &stick.down, &stick.left, &stick.right];
+    let active = keys.map(|key| self.keys.is_pressed(key));
+    let values = stick.values(x, y, active);
+    // Report releases before presses, so that switching sectors does
+    // not momentarily show both directions as pressed.
+    for (key, value) in keys.iter().zip(values) {
+      if value == 0.0 {
+        let () = self.on_axis(now, (*key).clone(), value);
+      }
+    }
+    for (key, value) in keys.iter().zip(values) {
+      if value != 0.0 {
+        let () = self.on_axis(now, (*key).clone(), value);
+      }
+    }
+  }
+
+  /// Handle a "tick", invoking `handler` for each overdue press or
+  /// repeat.
+  ///
+  /// Before `handler` is invoked, the key's repeat interval is set
+  /// based on its current deflection, by means of
+  /// [`KeyRepeat::Interval`]. `handler` may override that.
+  ///
+  /// See [`Keys::tick`].
+  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(&K, &mut KeyRepeat) -> C,
+    C: Default + BitOrAssign,
+  {
+    let max = self.keys.config().interval;
+    let min = self.min_interval;
+    let axes = &self.axes;
+    let result = self.keys.tick_with(
+      now,
+      |key, _fire, repeat| {
+        if let Some(axis) = axes.get(key) {
+          let Hysteresis { press, .. } = axis.thresholds;
+          let scale = if press < 1.0 {
+            ((axis.value - press) / (1.0 - press)).clamp(0.0, 1.0)
+          } else {
+            1.0
+          };
+          let range = max.saturating_sub(min);
+          *repeat = KeyRepeat::Interval(max.saturating_sub(range.mul_f32(scale)));
+        }
+        Ok::<_, Infallible>(handler(key, repeat))
+      },
+      |_key| (),
+    );
+    match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+
+
+  /// Create an [`Analog`] object pressing keys at a value of 0.5 and
+  /// releasing them below 0.3.
+  fn analog() -> Analog<&'static str> {
+    let thresholds = Hysteresis::new(0.5, 0.3);
+    Analog::new(thresholds, SECOND / 4, Config::new(SECOND, SECOND))
+  }
+
+
+  /// Check that axis values are translated into presses and releases
+  /// subject to hysteresis.
+  #[test]
+  fn hysteresis() {
+    let now = Instant::now();
+    let mut analog = analog();
+
+    let () = analog.on_axis(now, "trigger", 0.4);
+    assert!(!analog.keys().is_pressed("trigger"));
+    let () = analog.on_axis(now, "trigger", 0.5);
+    assert!(analog.keys().is_pressed("trigger"));
+    let () = analog.on_axis(now, "trigger", 0.35);
+    assert!(analog.keys().is_pressed("trigger"));
+    let () = analog.on_axis(now, "trigger", 0.2);
+    assert!(!analog.keys().is_pressed("trigger"));
+
+    let () = analog.set_thresholds("trigger", Hysteresis::new(0.9, 0.8));
+    let () = analog.on_axis(now, "trigger", 0.6);
+    assert!(!analog.keys().is_pressed("trigger"));
+    assert_eq!(analog.value("trigger"), 0.6);
+  }
+
+
+  /// Check that a release threshold above the press threshold is
+  /// clamped.
+  #[test]
+  fn inverted_thresholds() {
+    let now = Instant::now();
+    let thresholds = Hysteresis::new(0.3, 0.5);
+    assert_eq!(thresholds.release(), 0.3);
+
+    let mut analog = Analog::<&str>::new(thresholds, SECOND / 4, Config::new(SECOND, SECOND));
+    let () = analog.on_axis(now, "trigger", 0.4);
+    assert!(analog.keys().is_pressed("trigger"));
+    let () = analog.on_axis(now, "trigger", 0.4);
+    assert!(analog.keys().is_pressed("trigger"));
+  }
+
+
+  /// Check that the repeat interval scales with deflection.
+  #[test]
+  fn scaled_interval() {
+    let now = Instant::now();
+    let mut analog = analog();
+
+    let () = analog.on_axis(now, "right", 0.5);
+    let (_, next) = analog.tick(now, |_key, _repeat| true);
+    assert_eq!(next, Some(now + SECOND));
+    let (_, next) = analog.tick(now + SECOND, |_key, _repeat| true);
+    assert_eq!(next, Some(now + 2 * SECOND));
+
+    let () = analog.on_axis(now + SECOND, "right", 1.0);
+    let (_, next) = analog.tick(now + 2 * SECOND, |_key, _repeat| true);
+    assert_eq!(next, Some(now + 2 * SECOND + SECOND / 4));
+  }
+
+
+  /// Check that stick positions are mapped to directions.
+  #[test]
+  fn stick() {
+    let now = Instant::now();
+    let mut analog = analog();
+    let stick = Stick::new("up", "down", "left", "right").with_dead_zone(0.2);
+    let pressed = |analog: &Analog<&'static str>| {
+      let mut pressed = analog.keys().iter_pressed().copied().collect::<Vec<_>>();
+      let () = pressed.sort();
+      pressed
+    };
+
+    let () = analog.on_stick(now, &stick, 0.1, 0.1);
+    assert_eq!(pressed(&analog), Vec::<&str>::new());
+    let () = analog.on_stick(now, &stick, 0.8, 0.7);
+    assert_eq!(pressed(&analog), vec!["right"]);
+    let () = analog.on_stick(now, &stick, -0.1, -0.9);
+    assert_eq!(pressed(&analog), vec!["down"]);
+
+    let stick = stick.with_directions(Directions::Eight);
+    let () = analog.on_stick(now, &stick, -0.7, -0.7);
+    assert_eq!(pressed(&analog), vec!["down", "left"]);
+    let () = analog.on_stick(now, &stick, 0.0, 0.0);
+    assert_eq!(pressed(&analog), Vec::<&str>::new());
+  }
+
+
+  /// Check that a stick jittering around a sector boundary does not
+  /// switch between directions.
+  #[test]
+  fn stick_jitter() {
+    let now = Instant::now();
+    let mut analog = analog();
+    let stick = Stick::new("up", "down", "left", "right");
+    let pressed = |analog: &Analog<&'static str>| {
+      let mut pressed = analog.keys().iter_pressed().copied().collect::<Vec<_>>();
+      let () = pressed.sort();
+      pressed
+    };
+
+    let (sin, cos) = (FRAC_PI_4 - 0.001).sin_cos();
+    let () = analog.on_stick(now, &stick, cos, sin);
+    assert_eq!(pressed(&analog), vec!["right"]);
+
+    for i in 0..10 {
+      let jitter = if i % 2 == 0 { 0.001 } else { -0.001 };
+      let (sin, cos) = (FRAC_PI_4 + jitter).sin_cos();
+      let () = analog.on_stick(now, &stick, cos, sin);
+      assert_eq!(pressed(&analog), vec!["right"]);
+    }
+
+    // Moving past the dead band switches directions.
+    let (sin, cos) = (FRAC_PI_4 + 0.2).sin_cos();
+    let () = analog.on_stick(now, &stick, cos, sin);
+    assert_eq!(pressed(&analog), vec!["up"]);
+    let (sin, cos) = (FRAC_PI_4 - 0.001).sin_cos();
+    let () = analog.on_stick(now, &stick, cos, sin);
+    assert_eq!(pressed(&analog), vec!["up"]);
+
+    let stick = stick.with_angular_hysteresis(0.0);
+    let (sin, cos) = (FRAC_PI_4 - 0.01).sin_cos();
+    let () = analog.on_stick(now, &stick, cos, sin);
+    assert_eq!(pressed(&analog), vec!["right"]);
+  }
+}
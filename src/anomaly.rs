@@ -0,0 +1,196 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Reporting of unexpected conditions encountered while tracking keys.
+
+use std::collections::vec_deque::Drain;
+use std::collections::VecDeque;
+
+
+/// The maximum number of anomalies retained until retrieved.
+pub(crate) const MAX_ANOMALIES: usize = 64;
+
+
+/// An unexpected condition encountered by [`Keys`][crate::Keys].
+///
+/// Anomalies are collected by [`Keys`][crate::Keys] and can be
+/// retrieved via [`Keys::drain_anomalies`][crate::Keys::drain_anomalies].
+///
+/// Anomalies caused by input events, i.e., all but
+/// [`Stuck`][Anomaly::Stuck] and [`TimeJump`][Anomaly::TimeJump], are
+/// only reported once diagnostics got enabled via
+/// [`Keys::enable_diagnostics`][crate::Keys::enable_diagnostics].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Anomaly<K, I> {
+  /// A key was held down for longer than the configured maximum hold
+  /// duration and got force-released.
+  Stuck {
+    /// The key in question.
+    key: K,
+    /// The instant at which the key was pressed.
+    pressed_at: I,
+    /// The instant at which the key got force-released.
+    released_at: I,
+  },
+  /// Time advanced by more than the configured maximum gap between two
+  /// observed instants.
+  TimeJump {
+    /// The last instant observed before the jump.
+    from: I,
+    /// The first instant observed after the jump.
+    to: I,
+  },
+  /// An input event was timestamped earlier than possible, e.g.,
+  /// before the last "tick" or, for releases, before the corresponding
+  /// press.
+  ///
+  /// The event was treated as if it happened at the adjusted instant.
+  OutOfOrder {
+    /// The key in question.
+    key: K,
+    /// The instant at which the event was reported.
+    at: I,
+    /// The instant the event got adjusted to.
+    adjusted: I,
+  },
+  /// A key was released that was not known to be pressed.
+  ///
+  /// This can happen legitimately if the key's state got cleared in
+  /// the meantime, e.g., because it got force-released.
+  UnknownRelease {
+    /// The key in question.
+    key: K,
+    /// The instant at which the release was reported.
+    at: I,
+  },
+  /// A key was released by a device that was not holding it, e.g.,
+  /// because it had been released already.
+  DuplicateRelease {
+    /// The key in question.
+    key: K,
+    /// The instant at which the release was reported.
+    at: I,
+  },
+  /// A key was pressed by a device that was already holding it.
+  ///
+  /// Note that auto-repeat events generated by the system typically
+  /// manifest in this form.
+  DuplicatePress {
+    /// The key in question.
+    key: K,
+    /// The instant at which the press was reported.
+    at: I,
+  },
+}
+
+
+/// Counters of the anomalies encountered by [`Keys`][crate::Keys],
+/// by kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Diagnostics {
+  /// The number of [`Anomaly::Stuck`] reports.
+  pub stuck: u64,
+  /// The number of [`Anomaly::TimeJump`] reports.
+  pub time_jumps: u64,
+  /// The number of [`Anomaly::OutOfOrder`] reports.
+  pub out_of_order: u64,
+  /// The number of [`Anomaly::UnknownRelease`] reports.
+  pub unknown_releases: u64,
+  /// The number of [`Anomaly::DuplicateRelease`] reports.
+  pub duplicate_releases: u64,
+  /// The number of [`Anomaly::DuplicatePress`] reports.
+  pub duplicate_presses: u64,
+}
+
+impl Diagnostics {
+  fn count<K, I>(&mut self, anomaly: &Anomaly<K, I>) {
+    let counter = match anomaly {
+      Anomaly::Stuck { .. } => &mut self.stuck,
+      Anomaly::TimeJump { .. } => &mut self.time_jumps,
+      Anomaly::OutOfOrder { .. } => &mut self.out_of_order,
+      Anomaly::UnknownRelease { .. } => &mut self.unknown_releases,
+      Anomaly::DuplicateRelease { .. } => &mut self.duplicate_releases,
+      Anomaly::DuplicatePress { .. } => &mut self.duplicate_presses,
+    };
+    *counter = counter.saturating_add(1);
+  }
+}
+
+
+/// A log of anomalies not yet retrieved by the user.
+///
+/// The log retains at most [`MAX_ANOMALIES`] anomalies, dropping the
+/// oldest ones first, so that it does not grow without bounds if never
+/// drained.
+#[derive(Clone, Debug)]
+pub(crate) struct AnomalyLog<K, I> {
+  /// The anomalies encountered, oldest first.
+  anomalies: VecDeque<Anomaly<K, I>>,
+  /// Diagnostic counters, if enabled.
+  diagnostics: Option<Diagnostics>,
+  /// The function used for cloning keys, available once diagnostics
+  /// are enabled.
+  clone_key: Option<fn(&K) -> K>,
+}
+
+impl<K, I> AnomalyLog<K, I> {
+  pub(crate) fn new() -> Self {
+    Self {
+      anomalies: VecDeque::new(),
+      diagnostics: None,
+      clone_key: None,
+    }
+  }
+
+  /// Record an anomaly.
+  pub(crate) fn record(&mut self, anomaly: Anomaly<K, I>) {
+    if let Some(diagnostics) = &mut self.diagnostics {
+      let () = diagnostics.count(&anomaly);
+    }
+    if self.anomalies.len() >= MAX_ANOMALIES {
+      let _anomaly = self.anomalies.pop_front();
+    }
+    let () = self.anomalies.push_back(anomaly);
+  }
+
+  /// Record an anomaly caused by an input event, if diagnostics are
+  /// enabled.
+  pub(crate) fn record_input(&mut self, anomaly: Anomaly<K, I>) {
+    if self.diagnostics.is_some() {
+      let () = self.record(anomaly);
+    }
+  }
+
+  /// Record that an input event for `key` reported at `at` got
+  /// adjusted to `adjusted`, if diagnostics are enabled.
+  pub(crate) fn record_out_of_order(&mut self, key: &K, at: I, adjusted: I) {
+    if let Some(clone_key) = self.clone_key {
+      let () = self.record(Anomaly::OutOfOrder {
+        key: clone_key(key),
+        at,
+        adjusted,
+      });
+    }
+  }
+
+  #[inline]
+  pub(crate) fn enable_diagnostics(&mut self)
+  where
+    K: Clone,
+  {
+    let _diagnostics = self.diagnostics.get_or_insert_with(Diagnostics::default);
+    self.clone_key = Some(K::clone);
+  }
+
+  #[inline]
+  pub(crate) fn diagnostics(&self) -> Option<&Diagnostics> {
+    self.diagnostics.as_ref()
+  }
+
+  #[inline]
+  pub(crate) fn drain(&mut self) -> Drain<'_, Anomaly<K, I>> {
+    self.anomalies.drain(..)
+  }
+}
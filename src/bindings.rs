@@ -0,0 +1,571 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for mapping keys to application defined actions.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::mem::replace;
+use std::ops::BitOrAssign;
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::frame::Fire;
+use crate::Config;
+use crate::KeyRepeat;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// A key, optionally in combination with modifier keys that have to be
+/// held down at the time the key is pressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[non_exhaustive]
+pub struct Binding<K> {
+  /// The key in question.
+  pub key: K,
+  /// The modifier keys required, in no particular order.
+  pub modifiers: Vec<K>,
+}
+
+impl<K> Binding<K>
+where
+  K: Eq,
+{
+  /// Create a new [`Binding`] for `key`, without any modifiers.
+  pub fn new(key: K) -> Self {
+    Self {
+      key,
+      modifiers: Vec::new(),
+    }
+  }
+
+  /// Add `modifier` to the set of modifiers required.
+  pub fn with_modifier(mut self, modifier: K) -> Self {
+    if !self.modifiers.contains(&modifier) {
+      let () = self.modifiers.push(modifier);
+    }
+    self
+  }
+
+  /// Check whether `other` refers to the same key and set of
+  /// modifiers.
+  fn is_same(&self, other: &Self) -> bool {
+    self.key == other.key
+      && self.modifiers.len() == other.modifiers.len()
+      && self.modifiers.iter().all(|m| other.modifiers.contains(m))
+  }
+}
+
+
+/// The auto-repeat behavior of an action.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum RepeatPolicy {
+  /// The action is triggered once per key press and never repeated.
+  None,
+  /// The action is repeated as per the [`Config`] in use.
+  #[default]
+  Standard,
+  /// The action is repeated as per the [`Config`] in use, but with
+  /// each repeat shortening the interval by `step`, down to `min`.
+  Accelerated {
+    /// The amount by which each repeat shortens the interval.
+    step: Duration,
+    /// The minimum interval.
+    min: Duration,
+  },
+  /// The action is repeated at the provided interval, without any
+  /// initial timeout.
+  Turbo(Duration),
+}
+
+
+/// A single entry of a [`Keymap`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+struct Entry<K, A> {
+  /// The binding triggering the action.
+  binding: Binding<K>,
+  /// The action triggered.
+  action: A,
+  /// The auto-repeat behavior of the action.
+  #[cfg_attr(feature = "serde", serde(default))]
+  policy: RepeatPolicy,
+}
+
+
+/// A mapping from [`Binding`]s to actions of type `A`.
+///
+/// With the `serde` feature enabled, this type can be serialized and
+/// deserialized, e.g., for storing user defined keymaps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Keymap<K, A> {
+  /// The bindings, in order of their addition.
+  entries: Vec<Entry<K, A>>,
+}
+
+impl<K, A> Keymap<K, A>
+where
+  K: Eq,
+{
+  /// Create a new, empty [`Keymap`].
+  pub fn new() -> Self {
+    Self {
+      entries: Vec::new(),
+    }
+  }
+
+  /// Bind `action` with the given repeat policy to `binding`,
+  /// returning the action previously bound to it, if any.
+  pub fn bind(&mut self, binding: Binding<K>, action: A, policy: RepeatPolicy) -> Option<A> {
+    let entry = Entry {
+      binding,
+      action,
+      policy,
+    };
+
+    if let Some(existing) = self
+      .entries
+      .iter_mut()
+      .find(|existing| existing.binding.is_same(&entry.binding))
+    {
+      let prev = replace(existing, entry);
+      Some(prev.action)
+    } else {
+      let () = self.entries.push(entry);
+      None
+    }
+  }
+
+  /// Remove `binding`, returning the action bound to it, if any.
+  pub fn unbind(&mut self, binding: &Binding<K>) -> Option<A> {
+    let idx = self
+      .entries
+      .iter()
+      .position(|entry| entry.binding.is_same(binding))?;
+    Some(self.entries.remove(idx).action)
+  }
+
+  /// Look up the action and its repeat policy for `key`, given the
+  /// modifiers held down.
+  ///
+  /// The modifiers held have to match those of a binding exactly.
+  /// E.g., a binding with a single modifier does not match if an
+  /// additional modifier is held.
+  pub fn resolve(&self, key: &K, held: &[K]) -> Option<(&A, RepeatPolicy)> {
+    self
+      .entries
+      .iter()
+      .find(|entry| {
+        let binding = &entry.binding;
+        binding.key == *key
+          && binding.modifiers.len() == held.len()
+          && binding.modifiers.iter().all(|m| held.contains(m))
+      })
+      .map(|entry| (&entry.action, entry.policy))
+  }
+
+  /// Check whether `key` is used as a modifier in any binding.
+  fn is_modifier(&self, key: &K) -> bool {
+    self
+      .entries
+      .iter()
+      .any(|entry| entry.binding.modifiers.contains(key))
+  }
+}
+
+impl<K, A> Default for Keymap<K, A>
+where
+  K: Eq,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+
+/// A layer on top of [`Keys`] reporting actions of type `A` bound to
+/// keys via a [`Keymap`], instead of the keys themselves.
+///
+/// Actions are resolved based on the modifiers held down at the time
+/// the key got pressed. The keymap can be changed at any time, with
+/// keys already held down staying pressed and being reported with
+/// the action and repeat policy bound to them by then. Presses and
+/// repeats of keys not bound to an action are not reported.
+#[derive(Clone, Debug)]
+pub struct Bindings<K, A, I = Instant, D = ()> {
+  /// The keymap in use.
+  keymap: Keymap<K, A>,
+  /// The underlying key state.
+  keys: Keys<K, I, D>,
+  /// The modifiers held down at the time a key got pressed.
+  modifiers: HashMap<K, Vec<K>>,
+  /// The current interval of keys bound to accelerated actions.
+  intervals: HashMap<K, Duration>,
+}
+
+impl<K, A, I, D> Bindings<K, A, I, D>
+where
+  K: Clone + Eq + Hash,
+  D: Eq,
+  I: Timestamp,
+{
+  /// Create a new [`Bindings`] object using `keymap` and tracking keys
+  /// as per `config`.
+  pub fn new(keymap: Keymap<K, A>, config: Config) -> Self {
+    Self {
+      keymap,
+      keys: Keys::with_config(config),
+      modifiers: HashMap::new(),
+      intervals: HashMap::new(),
+    }
+  }
+
+  /// Retrieve the keymap in use.
+  #[inline]
+  pub fn keymap(&self) -> &Keymap<K, A> {
+    &self.keymap
+  }
+
+  /// Retrieve the keymap in use mutably, e.g., to rebind actions.
+  #[inline]
+  pub fn keymap_mut(&mut self) -> &mut Keymap<K, A> {
+    &mut self.keymap
+  }
+
+  /// Replace the keymap in use, returning the previous one.
+  #[inline]
+  pub fn set_keymap(&mut self, keymap: Keymap<K, A>) -> Keymap<K, A> {
+    replace(&mut self.keymap, keymap)
+  }
+
+  /// Retrieve the underlying [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<K, I, D> {
+    &self.keys
+  }
+
+  /// Take note of the modifiers held while pressing `key`.
+  fn on_press(&mut self, key: &K) {
+    if !self.keys.is_pressed(key) {
+      let held = self
+        .keys
+        .iter_pressed()
+        .filter(|held| self.keymap.is_modifier(held))
+        .cloned()
+        .collect();
+      let _prev = self.modifiers.insert(key.clone(), held);
+    }
+  }
+
+  /// This method is to be invoked on every key press received.
+  ///
+  /// See [`Keys::on_key_press`].
+  pub fn on_key_press(&mut self, now: I, key: K)
+  where
+    D: Default,
+  {
+    let () = self.on_press(&key);
+    self.keys.on_key_press(now, key)
+  }
+
+  /// This method is to be invoked on every key release received.
+  ///
+  /// See [`Keys::on_key_release`].
+  pub fn on_key_release(&mut self, now: I, key: K)
+  where
+    D: Default,
+  {
+    self.keys.on_key_release(now, key)
+  }
+
+  /// This method is to be invoked on every key press received from
+  /// `device`.
+  ///
+  /// See [`Keys::on_device_key_press`].
+  pub fn on_device_key_press(&mut self, now: I, device: D, key: K) {
+    let () = self.on_press(&key);
+    self.keys.on_device_key_press(now, device, key)
+  }
+
+  /// This method is to be invoked on every key release received from
+  /// `device`.
+  ///
+  /// See [`Keys::on_device_key_release`].
+  pub fn on_device_key_release(&mut self, now: I, device: D, key: K) {
+    self.keys.on_device_key_release(now, device, key)
+  }
+
+  /// Handle a "tick", invoking `handler` for each overdue press or
+  /// repeat of a key bound to an action.
+  ///
+  /// See [`Keys::tick`].
+  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(&A) -> C,
+    C: Default + BitOrAssign,
+  {
+    let interval = self.keys.config().interval;
+    let keymap = &self.keymap;
+    let modifiers = &self.modifiers;
+    let intervals = &mut self.intervals;
+    let mut removed = Vec::new();
+
+    let result = self.keys.tick_with(
+      now,
+      |key, fire, repeat| {
+        let held = modifiers.get(key).map(Vec::as_slice).unwrap_or_default();
+        // NB: We keep repeating keys that are not bound or whose
+        //     action is not to be repeated, as the keymap may change
+        //     while they are held down.
+        let Some((action, policy)) = keymap.resolve(key, held) else {
+          return Ok::<_, Infallible>(C::default())
+        };
+
+        *repeat = match (policy, fire) {
+          (RepeatPolicy::None, Fire::Press) => KeyRepeat::Enabled,
+          (RepeatPolicy::None, Fire::Repeat) => return Ok(C::default()),
+          (RepeatPolicy::Standard, Fire::Press) => KeyRepeat::Enabled,
+          // Switch back to the configured interval, in case the key
+          // got rebound from a different policy.
+          (RepeatPolicy::Standard, Fire::Repeat) => KeyRepeat::Interval(interval),
+          (RepeatPolicy::Accelerated { .. }, Fire::Press) => {
+            let _prev = intervals.remove(key);
+            KeyRepeat::Enabled
+          },
+          (RepeatPolicy::Accelerated { step, min }, Fire::Repeat) => {
+            let current = intervals.get(key).copied().unwrap_or(interval);
+            let next = current.saturating_sub(step).max(min);
+            let _prev = intervals.insert(key.clone(), next);
+            KeyRepeat::Interval(next)
+          },
+          (RepeatPolicy::Turbo(interval), Fire::Press) => KeyRepeat::Timeout(interval),
+          (RepeatPolicy::Turbo(interval), Fire::Repeat) => KeyRepeat::Interval(interval),
+        };
+        Ok(handler(action))
+      },
+      |key| removed.push(key.clone()),
+    );
+    let result = match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    };
+
+    for key in removed {
+      let _modifiers = self.modifiers.remove(&key);
+      let _interval = self.intervals.remove(&key);
+    }
+    result
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+
+
+  /// The actions bound in tests.
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+  enum Action {
+    Left,
+    SelectLeft,
+    Fire,
+    Scroll,
+  }
+
+
+  /// Create a [`Keymap`] binding actions with different repeat
+  /// policies.
+  fn keymap() -> Keymap<char, Action> {
+    let mut keymap = Keymap::new();
+    let _prev = keymap.bind(Binding::new('h'), Action::Left, RepeatPolicy::Standard);
+    let _prev = keymap.bind(
+      Binding::new('h').with_modifier('S'),
+      Action::SelectLeft,
+      RepeatPolicy::None,
+    );
+    let _prev = keymap.bind(
+      Binding::new('f'),
+      Action::Fire,
+      RepeatPolicy::Turbo(SECOND / 2),
+    );
+    let _prev = keymap.bind(
+      Binding::new('j'),
+      Action::Scroll,
+      RepeatPolicy::Accelerated {
+        step: SECOND / 4,
+        min: SECOND / 4,
+      },
+    );
+    keymap
+  }
+
+  /// Collect the actions reported by `tick`s at the provided instants.
+  fn collect(
+    bindings: &mut Bindings<char, Action>,
+    now: Instant,
+    ticks: impl IntoIterator<Item = Duration>,
+  ) -> Vec<(Duration, Action)> {
+    let mut actions = Vec::new();
+    for offset in ticks {
+      let _result = bindings.tick(now + offset, |action| {
+        let () = actions.push((offset, *action));
+        true
+      });
+    }
+    actions
+  }
+
+
+  /// Check that actions are resolved based on the modifiers held.
+  #[test]
+  fn modifiers() {
+    let now = Instant::now();
+    let mut bindings = Bindings::new(keymap(), Config::new(5 * SECOND, SECOND));
+
+    let () = bindings.on_key_press(now, 'S');
+    let () = bindings.on_key_press(now, 'h');
+    // Releasing the modifier has no effect on the action.
+    let () = bindings.on_key_release(now + SECOND, 'S');
+    let actions = collect(&mut bindings, now, [0, 7].map(|s| s * SECOND));
+    assert_eq!(actions, vec![(Duration::ZERO, Action::SelectLeft)]);
+
+    let () = bindings.on_key_release(now + 8 * SECOND, 'h');
+    let () = bindings.on_key_press(now + 9 * SECOND, 'h');
+    let actions = collect(&mut bindings, now, [9, 15].map(|s| s * SECOND));
+    assert_eq!(
+      actions,
+      vec![
+        (9 * SECOND, Action::Left),
+        (15 * SECOND, Action::Left),
+        (15 * SECOND, Action::Left),
+      ]
+    );
+  }
+
+
+  /// Check that the different repeat policies work as expected.
+  #[test]
+  fn repeat_policies() {
+    let now = Instant::now();
+    let mut bindings = Bindings::new(keymap(), Config::new(5 * SECOND, SECOND));
+
+    let () = bindings.on_key_press(now, 'f');
+    let actions = collect(&mut bindings, now, [0, 1, 2].map(|s| s * SECOND / 2));
+    assert_eq!(actions.len(), 3);
+    let () = bindings.on_key_release(now + SECOND, 'f');
+
+    // Accelerated repeats should happen at 7s, 7.75s, 8.25s, and from
+    // there on every 0.25s.
+    let () = bindings.on_key_press(now + 2 * SECOND, 'j');
+    let mut ticks = vec![2 * SECOND];
+    let () = ticks.extend((28..=36).map(|i| i * SECOND / 4));
+    let actions = collect(&mut bindings, now, ticks);
+    let offsets = actions
+      .iter()
+      .map(|(offset, _action)| offset.as_millis())
+      .collect::<Vec<_>>();
+    assert_eq!(offsets, vec![2000, 7000, 7750, 8250, 8500, 8750, 9000]);
+  }
+
+
+  /// Check that rebinding works while keys are held down.
+  #[test]
+  fn rebind() {
+    let now = Instant::now();
+    let mut bindings = Bindings::new(keymap(), Config::new(5 * SECOND, SECOND));
+
+    let () = bindings.on_key_press(now, 'h');
+    let actions = collect(&mut bindings, now, [Duration::ZERO]);
+    assert_eq!(actions, vec![(Duration::ZERO, Action::Left)]);
+
+    let _prev =
+      bindings
+        .keymap_mut()
+        .bind(Binding::new('h'), Action::Scroll, RepeatPolicy::Standard);
+    let actions = collect(&mut bindings, now, [5 * SECOND]);
+    assert_eq!(actions, vec![(5 * SECOND, Action::Scroll)]);
+    assert!(bindings.keys().is_pressed(&'h'));
+  }
+
+
+  /// Check that keys held down start repeating once bound.
+  #[test]
+  fn bind_held() {
+    let now = Instant::now();
+    let mut bindings = Bindings::new(Keymap::new(), Config::new(5 * SECOND, SECOND));
+
+    let () = bindings.on_key_press(now, 'f');
+    assert_eq!(collect(&mut bindings, now, [Duration::ZERO]), Vec::new());
+
+    let _prev = bindings
+      .keymap_mut()
+      .bind(Binding::new('f'), Action::Fire, RepeatPolicy::Standard);
+    let actions = collect(&mut bindings, now, [7 * SECOND]);
+    assert_eq!(
+      actions,
+      vec![
+        (7 * SECOND, Action::Fire),
+        (7 * SECOND, Action::Fire),
+        (7 * SECOND, Action::Fire),
+      ]
+    );
+  }
+
+
+  /// Check that modifiers have to match exactly.
+  #[test]
+  fn exact_modifiers() {
+    let now = Instant::now();
+    let mut keymap = Keymap::new();
+    let _prev = keymap.bind(
+      Binding::new('h').with_modifier('C'),
+      Action::Left,
+      RepeatPolicy::None,
+    );
+    let _prev = keymap.bind(
+      Binding::new('h').with_modifier('S'),
+      Action::SelectLeft,
+      RepeatPolicy::None,
+    );
+    let mut bindings = Bindings::new(keymap, Config::new(5 * SECOND, SECOND));
+
+    let () = bindings.on_key_press(now, 'C');
+    let () = bindings.on_key_press(now, 'S');
+    let () = bindings.on_key_press(now, 'h');
+    assert_eq!(collect(&mut bindings, now, [Duration::ZERO]), Vec::new());
+
+    let () = bindings.on_key_release(now + SECOND, 'h');
+    let () = bindings.on_key_release(now + SECOND, 'S');
+    let () = bindings.on_key_press(now + 2 * SECOND, 'h');
+    assert_eq!(
+      collect(&mut bindings, now, [2 * SECOND]),
+      vec![(2 * SECOND, Action::Left)]
+    );
+  }
+
+  /// Check that a keymap can be serialized and deserialized.
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serialize_keymap() {
+    let keymap = keymap();
+    let json = serde_json::to_string(&keymap).unwrap();
+    let restored = serde_json::from_str::<Keymap<char, Action>>(&json).unwrap();
+    assert_eq!(restored, keymap);
+  }
+}
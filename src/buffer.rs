@@ -0,0 +1,203 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for buffering key presses for a window of time.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::frame::Fire;
+use crate::Config;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// An input buffer on top of [`Keys`], as commonly used by games to
+/// accept an action shortly after the corresponding key went down.
+///
+/// A press stays buffered for a configurable window, during which it
+/// can be [consumed][InputBuffer::consume] once. Presses not consumed
+/// within their window expire. Optionally, auto-repeats of a key held
+/// down refresh its buffered press.
+#[derive(Clone, Debug)]
+pub struct InputBuffer<K, I = Instant> {
+  /// The duration for which presses stay buffered.
+  window: Duration,
+  /// Whether repeats refresh the buffered press of a key.
+  refresh: bool,
+  /// Buffered presses, along with the instant they got buffered at.
+  buffered: HashMap<K, I>,
+  /// The underlying key state.
+  keys: Keys<K, I>,
+}
+
+impl<K, I> InputBuffer<K, I>
+where
+  K: Clone + Eq + Hash,
+  I: Timestamp,
+{
+  /// Create a new [`InputBuffer`] object, buffering presses for
+  /// `window` and generating repeats as per `config`.
+  pub fn new(window: Duration, config: Config) -> Self {
+    Self {
+      window,
+      refresh: false,
+      buffered: HashMap::new(),
+      keys: Keys::with_config(config),
+    }
+  }
+
+  /// Set whether auto-repeats of a key held down refresh its buffered
+  /// press, restarting the window.
+  ///
+  /// Repeats are not taken into account by default.
+  #[inline]
+  pub fn set_refresh(&mut self, refresh: bool) {
+    self.refresh = refresh
+  }
+
+  /// Retrieve the underlying [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<K, I> {
+    &self.keys
+  }
+
+  /// Check whether a press of `key` is buffered at `now`.
+  pub fn is_buffered<Q>(&self, key: &Q, now: I) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+  {
+    self
+      .buffered
+      .get(key)
+      .is_some_and(|at| now < *at + self.window)
+  }
+
+  /// Consume the buffered press of `key`, if any.
+  ///
+  /// Returns `true` if a press was buffered at `now`, i.e., less than
+  /// the window ago. A buffered press can only be consumed once.
+  pub fn consume<Q>(&mut self, key: &Q, now: I) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+  {
+    self
+      .buffered
+      .remove(key)
+      .is_some_and(|at| now < at + self.window)
+  }
+
+  /// Drop all buffered presses.
+  #[inline]
+  pub fn clear(&mut self) {
+    let () = self.buffered.clear();
+  }
+
+  /// This method is to be invoked on every key press received.
+  pub fn on_key_press(&mut self, now: I, key: K) {
+    if !self.keys.is_pressed(&key) {
+      let _prev = self.buffered.insert(key.clone(), now);
+    }
+    self.keys.on_key_press(now, key)
+  }
+
+  /// This method is to be invoked on every key release received.
+  ///
+  /// Releasing a key does not affect its buffered press.
+  pub fn on_key_release(&mut self, now: I, key: K) {
+    self.keys.on_key_release(now, key)
+  }
+
+  /// Handle a "tick", expiring presses that have not been consumed
+  /// within the window and refreshing presses of keys that repeated,
+  /// if enabled.
+  ///
+  /// The instant at which the next "tick" should occur is returned (if
+  /// any).
+  pub fn tick(&mut self, now: I) -> Option<I> {
+    let refresh = self.refresh;
+    let buffered = &mut self.buffered;
+    let result = self.keys.tick_with(
+      now,
+      |key, fire, _repeat| {
+        if refresh && fire == Fire::Repeat {
+          let _prev = buffered.insert(key.clone(), now);
+        }
+        Ok::<_, Infallible>(false)
+      },
+      |_key| (),
+    );
+    let (_change, next_tick) = match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    };
+
+    let window = self.window;
+    let () = self.buffered.retain(|_key, at| now < *at + window);
+
+    let expiry = self.buffered.values().map(|at| *at + window).min();
+    match (next_tick, expiry) {
+      (Some(next), Some(expiry)) => Some(next.min(expiry)),
+      (next, expiry) => next.or(expiry),
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+  const WINDOW: Duration = Duration::from_millis(100);
+
+
+  /// Check that presses can be consumed exactly once within the
+  /// window.
+  #[test]
+  fn consume() {
+    let now = Instant::now();
+    let mut buffer = InputBuffer::new(WINDOW, Config::new(5 * SECOND, SECOND));
+
+    let () = buffer.on_key_press(now, "jump");
+    let () = buffer.on_key_release(now, "jump");
+    assert!(buffer.is_buffered("jump", now + WINDOW / 2));
+    assert!(!buffer.is_buffered("jump", now + WINDOW));
+    assert!(buffer.consume("jump", now + WINDOW / 2));
+    assert!(!buffer.consume("jump", now + WINDOW / 2));
+
+    // Unconsumed presses expire.
+    let () = buffer.on_key_press(now + SECOND, "jump");
+    let () = buffer.on_key_release(now + SECOND, "jump");
+    let next = buffer.tick(now + SECOND);
+    assert_eq!(next, Some(now + SECOND + WINDOW));
+    assert!(!buffer.is_buffered("jump", now + SECOND + WINDOW));
+    assert_eq!(buffer.tick(now + SECOND + WINDOW), None);
+    assert!(!buffer.consume("jump", now + SECOND + WINDOW / 2));
+  }
+
+
+  /// Check that repeats refresh the buffered press, if desired.
+  #[test]
+  fn refresh() {
+    let now = Instant::now();
+    let mut buffer = InputBuffer::new(WINDOW, Config::new(5 * SECOND, SECOND));
+
+    let () = buffer.on_key_press(now, "fire");
+    let _next = buffer.tick(now);
+    let _next = buffer.tick(now + 5 * SECOND);
+    assert!(!buffer.is_buffered("fire", now + 5 * SECOND));
+
+    let () = buffer.set_refresh(true);
+    let _next = buffer.tick(now + 6 * SECOND);
+    assert!(buffer.consume("fire", now + 6 * SECOND));
+    assert!(!buffer.consume("fire", now + 6 * SECOND));
+  }
+}
@@ -0,0 +1,202 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for estimating the system's auto-repeat timings.
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Config;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// The maximum number of samples retained per timing, with older ones
+/// being dropped first.
+const MAX_SAMPLES: usize = 32;
+
+
+/// Record `sample`, dropping the oldest sample if the maximum number
+/// has been reached.
+fn push_sample(samples: &mut VecDeque<Duration>, sample: Duration) {
+  if samples.len() >= MAX_SAMPLES {
+    let _sample = samples.pop_front();
+  }
+  let () = samples.push_back(sample);
+}
+
+
+/// Calculate the median of the provided durations.
+fn median(durations: &VecDeque<Duration>) -> Option<Duration> {
+  let mut durations = durations.iter().copied().collect::<Vec<_>>();
+  let () = durations.sort_unstable();
+
+  let mid = durations.len() / 2;
+  match durations.len() {
+    0 => None,
+    len if len % 2 == 0 => Some((durations[mid - 1] + durations[mid]) / 2),
+    _ => Some(durations[mid]),
+  }
+}
+
+
+/// A helper for estimating the system's auto-repeat timeout and
+/// interval by watching the key events it generates.
+///
+/// Most windowing systems report auto-repeats as additional key
+/// presses. By feeding all key presses and releases of such a system to
+/// a [`Calibration`] object, the timings in effect can be inferred and
+/// used for configuring a [`Keys`] object, so that auto-repeat driven
+/// by raw device events feels the same as the user's desktop.
+#[derive(Debug)]
+pub struct Calibration<K, I = Instant> {
+  /// The key currently being observed, along with the instants of its
+  /// initial press and its most recent repeat (if any).
+  current: Option<(K, I, Option<I>)>,
+  /// The most recent samples of the delay between the initial press
+  /// of a key and its first repeat.
+  timeouts: VecDeque<Duration>,
+  /// The most recent samples of the delay between two consecutive
+  /// repeats.
+  intervals: VecDeque<Duration>,
+}
+
+impl<K, I> Calibration<K, I>
+where
+  K: Eq + Hash,
+  I: Timestamp,
+{
+  /// Create a new [`Calibration`] object.
+  pub fn new() -> Self {
+    Self {
+      current: None,
+      timeouts: VecDeque::new(),
+      intervals: VecDeque::new(),
+    }
+  }
+
+  /// This method is to be invoked on every key press received,
+  /// including system generated auto-repeats.
+  pub fn on_key_press(&mut self, now: I, key: K) {
+    match &mut self.current {
+      Some((current, pressed_at, last_repeat)) if *current == key => {
+        let (samples, last) = match last_repeat {
+          None => (&mut self.timeouts, *pressed_at),
+          Some(last_repeat) => (&mut self.intervals, *last_repeat),
+        };
+        let () = push_sample(samples, now - last.min(now));
+        *last_repeat = Some(now);
+      },
+      _ => {
+        // Systems typically only repeat the most recently pressed key,
+        // so that is the one we observe.
+        self.current = Some((key, now, None));
+      },
+    }
+  }
+
+  /// This method is to be invoked on every key release received.
+  pub fn on_key_release(&mut self, _now: I, key: K) {
+    if matches!(&self.current, Some((current, ..)) if *current == key) {
+      self.current = None;
+    }
+  }
+
+  /// Retrieve the estimated timings in the form of a [`Config`].
+  ///
+  /// Estimates are based on the most recent samples only, so that they
+  /// adjust to changes of the system settings over time.
+  ///
+  /// `None` is returned if not enough auto-repeats have been observed
+  /// yet to estimate both timeout and interval.
+  pub fn config(&self) -> Option<Config> {
+    let timeout = median(&self.timeouts)?;
+    let interval = median(&self.intervals)?;
+    Some(Config::new(timeout, interval))
+  }
+
+  /// Create a [`Keys`] object using the estimated timings.
+  ///
+  /// `None` is returned if not enough auto-repeats have been observed
+  /// yet to estimate both timeout and interval.
+  pub fn keys<D>(&self) -> Option<Keys<K, I, D>>
+  where
+    D: Eq,
+  {
+    self.config().map(Keys::with_config)
+  }
+}
+
+impl<K, I> Default for Calibration<K, I>
+where
+  K: Eq + Hash,
+  I: Timestamp,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MILLIS: Duration = Duration::from_millis(1);
+
+
+  /// Check that we can estimate timings from system generated
+  /// auto-repeats.
+  #[test]
+  fn calibration() {
+    let now = Instant::now();
+    let mut calibration = Calibration::<char>::new();
+    assert_eq!(calibration.config(), None);
+
+    let () = calibration.on_key_press(now, 'a');
+    let () = calibration.on_key_release(now + 100 * MILLIS, 'a');
+    assert_eq!(calibration.config(), None);
+
+    let () = calibration.on_key_press(now + 1000 * MILLIS, 'b');
+    let () = calibration.on_key_press(now + 1660 * MILLIS, 'b');
+    let () = calibration.on_key_press(now + 1700 * MILLIS, 'b');
+    let () = calibration.on_key_press(now + 1741 * MILLIS, 'b');
+    let () = calibration.on_key_press(now + 1780 * MILLIS, 'b');
+    // Pressing another key stops repetition of the previous one.
+    let () = calibration.on_key_press(now + 1790 * MILLIS, 'c');
+    let () = calibration.on_key_release(now + 1800 * MILLIS, 'b');
+    let () = calibration.on_key_press(now + 2450 * MILLIS, 'c');
+    let () = calibration.on_key_release(now + 2460 * MILLIS, 'c');
+
+    let config = calibration.config().unwrap();
+    assert_eq!(config.timeout, 660 * MILLIS);
+    assert_eq!(config.interval, 40 * MILLIS);
+
+    let keys = calibration.keys::<()>().unwrap();
+    assert_eq!(keys.config(), &config);
+  }
+
+
+  /// Check that estimates are based on recent samples only.
+  #[test]
+  fn calibration_recent() {
+    let now = Instant::now();
+    let mut calibration = Calibration::<char>::new();
+
+    let () = calibration.on_key_press(now, 'a');
+    for i in 0..1000 {
+      let () = calibration.on_key_press(now + 500 * MILLIS + i * 40 * MILLIS, 'a');
+    }
+    let later = now + 60_000 * MILLIS;
+    for i in 0..MAX_SAMPLES as u32 {
+      let () = calibration.on_key_press(later + i * 20 * MILLIS, 'a');
+    }
+    assert_eq!(calibration.intervals.len(), MAX_SAMPLES);
+
+    let config = calibration.config().unwrap();
+    assert_eq!(config.timeout, 500 * MILLIS);
+    assert_eq!(config.interval, 20 * MILLIS);
+  }
+}
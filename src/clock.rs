@@ -0,0 +1,335 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Clock abstractions for reading the current time.
+
+use std::cell::Cell;
+use std::hash::Hash;
+use std::ops::BitOrAssign;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Config;
+use crate::KeyRepeat;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// A source of the current time.
+pub trait Clock {
+  /// The type of instant reported by the clock.
+  type Instant: Timestamp;
+
+  /// Retrieve the current time.
+  fn now(&self) -> Self::Instant;
+}
+
+impl<C> Clock for &C
+where
+  C: Clock + ?Sized,
+{
+  type Instant = C::Instant;
+
+  #[inline]
+  fn now(&self) -> Self::Instant {
+    C::now(self)
+  }
+}
+
+impl<C> Clock for Rc<C>
+where
+  C: Clock + ?Sized,
+{
+  type Instant = C::Instant;
+
+  #[inline]
+  fn now(&self) -> Self::Instant {
+    C::now(self)
+  }
+}
+
+
+/// A [`Clock`] reporting the system's monotonic time, as per
+/// [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  type Instant = Instant;
+
+  #[inline]
+  fn now(&self) -> Self::Instant {
+    Instant::now()
+  }
+}
+
+
+/// A [`Clock`] that only advances when explicitly told to, for usage
+/// in tests.
+///
+/// Because [`Clock`] is implemented for references and [`Rc`], a
+/// single [`MockClock`] can be shared between a [`ClockedKeys`] object
+/// and the code controlling time:
+/// ```
+/// # use std::time::Duration;
+/// # use std::time::Instant;
+/// # use keypeat::ClockedKeys;
+/// # use keypeat::MockClock;
+/// let clock = MockClock::new(Instant::now());
+/// let mut keys = ClockedKeys::<char, _>::new(&clock, Duration::from_secs(5), Duration::from_secs(1));
+/// let () = keys.on_key_press('a');
+/// let (_, next) = keys.tick(|_key, _repeat| true);
+/// assert_eq!(next, Some(clock.now() + Duration::from_secs(5)));
+///
+/// let () = clock.advance(Duration::from_secs(5));
+/// let (fired, _) = keys.tick(|_key, _repeat| true);
+/// assert!(fired);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MockClock<I = Instant>
+where
+  I: Copy,
+{
+  /// The current time.
+  now: Cell<I>,
+}
+
+impl<I> MockClock<I>
+where
+  I: Timestamp,
+{
+  /// Create a new [`MockClock`] starting at `start`.
+  pub fn new(start: I) -> Self {
+    Self {
+      now: Cell::new(start),
+    }
+  }
+
+  /// Retrieve the current time.
+  #[inline]
+  pub fn now(&self) -> I {
+    self.now.get()
+  }
+
+  /// Advance the clock by `by`.
+  pub fn advance(&self, by: Duration) {
+    let () = self.now.set(self.now.get() + by);
+  }
+
+  /// Set the clock to `now`.
+  ///
+  /// `now` may be earlier than the current time, e.g., to test the
+  /// handling of out-of-order input.
+  pub fn set(&self, now: I) {
+    let () = self.now.set(now);
+  }
+}
+
+impl<I> Clock for MockClock<I>
+where
+  I: Timestamp,
+{
+  type Instant = I;
+
+  #[inline]
+  fn now(&self) -> Self::Instant {
+    self.now.get()
+  }
+}
+
+
+/// A wrapper around [`Keys`] that reads the current time from a
+/// [`Clock`] instead of requiring it to be passed in explicitly.
+///
+/// Only methods for feeding input events into the wrapped object, as
+/// well as [`tick`][ClockedKeys::tick], are provided, each
+/// corresponding to the [`Keys`] method of the same name. All other
+/// functionality, e.g., queries of key state, is available on the
+/// wrapped object, as accessible via [`keys`][ClockedKeys::keys] and
+/// [`keys_mut`][ClockedKeys::keys_mut].
+#[derive(Clone, Debug)]
+pub struct ClockedKeys<K, C = SystemClock, D = ()>
+where
+  C: Clock,
+{
+  /// The wrapped [`Keys`] object.
+  keys: Keys<K, C::Instant, D>,
+  /// The clock to read time from.
+  clock: C,
+}
+
+impl<K, C, D> ClockedKeys<K, C, D>
+where
+  K: Eq + Hash,
+  D: Eq,
+  C: Clock,
+{
+  /// Create a new [`ClockedKeys`] object reading time from `clock`,
+  /// using `timeout` and `interval` for key repetition.
+  pub fn new(clock: C, timeout: Duration, interval: Duration) -> Self {
+    Self::with_config(clock, Config::new(timeout, interval))
+  }
+
+  /// Create a new [`ClockedKeys`] object reading time from `clock` and
+  /// using the provided configuration.
+  pub fn with_config(clock: C, config: Config) -> Self {
+    Self::from_keys(clock, Keys::with_config(config))
+  }
+
+  /// Wrap an existing [`Keys`] object, reading time from `clock` from
+  /// here on.
+  pub fn from_keys(clock: C, keys: Keys<K, C::Instant, D>) -> Self {
+    Self { keys, clock }
+  }
+
+  /// Retrieve the clock in use.
+  #[inline]
+  pub fn clock(&self) -> &C {
+    &self.clock
+  }
+
+  /// Retrieve the wrapped [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<K, C::Instant, D> {
+    &self.keys
+  }
+
+  /// Retrieve the wrapped [`Keys`] object mutably.
+  #[inline]
+  pub fn keys_mut(&mut self) -> &mut Keys<K, C::Instant, D> {
+    &mut self.keys
+  }
+
+  /// Destructure the object into the wrapped [`Keys`] object and the
+  /// clock.
+  #[inline]
+  pub fn into_parts(self) -> (Keys<K, C::Instant, D>, C) {
+    (self.keys, self.clock)
+  }
+
+  /// See [`Keys::on_key_press`].
+  pub fn on_key_press(&mut self, key: K)
+  where
+    D: Default,
+  {
+    self.keys.on_key_press(self.clock.now(), key)
+  }
+
+  /// See [`Keys::on_key_repeat`].
+  pub fn on_key_repeat(&mut self, key: K)
+  where
+    D: Default,
+  {
+    self.keys.on_key_repeat(self.clock.now(), key)
+  }
+
+  /// See [`Keys::on_key_release`].
+  pub fn on_key_release(&mut self, key: K)
+  where
+    D: Default,
+  {
+    self.keys.on_key_release(self.clock.now(), key)
+  }
+
+  /// See [`Keys::on_device_key_press`].
+  pub fn on_device_key_press(&mut self, device: D, key: K) {
+    self.keys.on_device_key_press(self.clock.now(), device, key)
+  }
+
+  /// See [`Keys::on_device_key_repeat`].
+  pub fn on_device_key_repeat(&mut self, device: D, key: K) {
+    self
+      .keys
+      .on_device_key_repeat(self.clock.now(), device, key)
+  }
+
+  /// See [`Keys::on_device_key_release`].
+  pub fn on_device_key_release(&mut self, device: D, key: K) {
+    self
+      .keys
+      .on_device_key_release(self.clock.now(), device, key)
+  }
+
+  /// See [`Keys::release_device`].
+  pub fn release_device(&mut self, device: &D) {
+    self.keys.release_device(self.clock.now(), device)
+  }
+
+  /// See [`Keys::release_all`].
+  pub fn release_all(&mut self) {
+    self.keys.release_all(self.clock.now())
+  }
+
+  /// See [`Keys::forget_device`].
+  pub fn forget_device(&mut self, device: &D) {
+    self.keys.forget_device(device)
+  }
+
+  /// See [`Keys::tick`].
+  pub fn tick<F, R>(&mut self, handler: F) -> (R, Option<C::Instant>)
+  where
+    F: FnMut(&K, &mut KeyRepeat) -> R,
+    R: Default + BitOrAssign,
+  {
+    self.keys.tick(self.clock.now(), handler)
+  }
+
+  /// See [`Keys::clear`].
+  pub fn clear(&mut self) {
+    self.keys.clear()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::FrameInstant;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+
+
+  /// Check that a [`MockClock`] can be advanced and set.
+  #[test]
+  fn mock_clock() {
+    let clock = MockClock::new(FrameInstant::<1>::new(10));
+    assert_eq!(clock.now(), FrameInstant::new(10));
+
+    let () = clock.advance(3 * SECOND);
+    assert_eq!(Clock::now(&clock), FrameInstant::new(13));
+
+    let () = clock.set(FrameInstant::new(2));
+    assert_eq!(Clock::now(&Rc::new(&clock)), FrameInstant::new(2));
+  }
+
+
+  /// Check that [`ClockedKeys`] reads time from the provided clock.
+  #[test]
+  fn clocked_keys() {
+    let start = Instant::now();
+    let clock = Rc::new(MockClock::new(start));
+    let mut keys = ClockedKeys::<char, _>::new(Rc::clone(&clock), 5 * SECOND, SECOND);
+    let mut fired = 0;
+    let mut handler = |_key: &char, _repeat: &mut KeyRepeat| {
+      fired += 1;
+      true
+    };
+
+    let () = keys.on_key_press('a');
+    let (_, next) = keys.tick(&mut handler);
+    assert_eq!(next, Some(start + 5 * SECOND));
+
+    let () = clock.advance(7 * SECOND);
+    let (_, next) = keys.tick(&mut handler);
+    assert_eq!(next, Some(start + 8 * SECOND));
+
+    let () = clock.advance(SECOND / 2);
+    let () = keys.on_key_release('a');
+    let (_, next) = keys.tick(&mut handler);
+    assert_eq!(next, None);
+    assert_eq!(fired, 4);
+  }
+}
@@ -0,0 +1,143 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Configuration of key repetition behavior.
+
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+
+/// The default maximum number of repeats of a single key that are
+/// caught up on at once.
+pub(crate) const DEFAULT_MAX_CATCH_UP: usize = 100;
+
+
+/// Retrieve the default value of [`Config::max_catch_up`].
+#[cfg(feature = "serde")]
+fn default_max_catch_up() -> Option<usize> {
+  Some(DEFAULT_MAX_CATCH_UP)
+}
+
+
+/// The source of key repeats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum RepeatMode {
+  /// Repeats are always synthesized. Repeats generated by the platform
+  /// are ignored.
+  #[default]
+  Synthesize,
+  /// Repeats generated by the platform are forwarded. Repeats are only
+  /// synthesized for as long as the platform has not been observed to
+  /// generate any.
+  ///
+  /// This mode is useful when it is not known upfront whether the
+  /// platform provides repeats, e.g., because some but not all Wayland
+  /// compositors do.
+  Hybrid,
+  /// No repeats are generated at all. Only the initial press of each
+  /// key is reported, as if auto-repeat got disabled for it on every
+  /// press.
+  ///
+  /// This mode reflects auto-repeat having been turned off in the
+  /// system settings.
+  Disabled,
+}
+
+
+/// Configuration of the timings used by [`Keys`][crate::Keys].
+///
+/// With the `serde` feature enabled, this type can be serialized and
+/// deserialized, e.g., for storage in a settings file. All but the
+/// timeout and interval are optional in this case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[non_exhaustive]
+pub struct Config {
+  /// The "timeout" after the initial key press after which the first
+  /// repeat is issued.
+  pub timeout: Duration,
+  /// The interval for any subsequent repeats.
+  pub interval: Duration,
+  /// The maximum duration a key may be held down before it is
+  /// considered stuck.
+  ///
+  /// A stuck key is force-released once this duration has elapsed and
+  /// reported as [`Anomaly::Stuck`][crate::Anomaly::Stuck]. That can
+  /// help in cases where a release event is never received, e.g.,
+  /// because the window lost focus while the key was held down.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub max_hold: Option<Duration>,
+  /// The maximum gap between two consecutively observed instants that
+  /// is still considered regular passage of time.
+  ///
+  /// Larger gaps, as may be caused by a system suspend, are treated as
+  /// a discontinuity: all repeat schedules are shifted forward by the
+  /// gap, so that no catch-up repeats are delivered for the time in
+  /// between. The instants at which keys got pressed are unaffected.
+  /// Each such gap is reported as
+  /// [`Anomaly::TimeJump`][crate::Anomaly::TimeJump].
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub max_gap: Option<Duration>,
+  /// The maximum number of repeats of a single key that are caught up
+  /// on at once.
+  ///
+  /// If a "tick" happens late, or a key is released long after its
+  /// last repeat got delivered, all repeats in between are due. Any
+  /// beyond this number are skipped. Defaults to 100. `None` lifts the
+  /// limit, which can result in an excessive number of repeats being
+  /// delivered for short intervals.
+  #[cfg_attr(feature = "serde", serde(default = "default_max_catch_up"))]
+  pub max_catch_up: Option<usize>,
+  /// The source of key repeats.
+  ///
+  /// Platform generated repeats are to be reported via
+  /// [`Keys::on_key_repeat`][crate::Keys::on_key_repeat].
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub mode: RepeatMode,
+}
+
+impl Config {
+  /// Create a new [`Config`] object using `timeout` as the initial
+  /// timeout after which pressed keys transition into auto-repeat mode
+  /// at interval `interval`.
+  pub fn new(timeout: Duration, interval: Duration) -> Self {
+    Self {
+      timeout,
+      interval,
+      max_hold: None,
+      max_gap: None,
+      max_catch_up: Some(DEFAULT_MAX_CATCH_UP),
+      mode: RepeatMode::Synthesize,
+    }
+  }
+}
+
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+  use super::*;
+
+
+  /// Check that we can deserialize a [`Config`] with optional fields
+  /// omitted.
+  #[test]
+  fn deserialize_config() {
+    let json = r#"{"timeout":{"secs":1,"nanos":0},"interval":{"secs":0,"nanos":50000000}}"#;
+    let config = serde_json::from_str::<Config>(json).unwrap();
+    assert_eq!(
+      config,
+      Config::new(Duration::from_secs(1), Duration::from_millis(50))
+    );
+
+    let mut config = config;
+    config.max_hold = Some(Duration::from_secs(30));
+    config.mode = RepeatMode::Hybrid;
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+  }
+}
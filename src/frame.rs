@@ -0,0 +1,118 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for querying key state once per frame.
+
+
+/// The kinds of key events reported by [`Keys::tick`][crate::Keys::tick].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fire {
+  /// The initial press of a key.
+  Press,
+  /// An auto-repeat of a key.
+  Repeat,
+}
+
+
+/// Add `key` to `keys`, unless it was the last one added.
+///
+/// All fires of a key are reported in succession, so checking the last
+/// element suffices to weed out duplicates.
+pub(crate) fn push_unique<K>(keys: &mut Vec<K>, key: &K)
+where
+  K: Clone + Eq,
+{
+  if keys.last() != Some(key) {
+    let () = keys.push(key.clone());
+  }
+}
+
+
+/// The key state for a single frame, as reported by
+/// [`Keys::frame`][crate::Keys::frame].
+///
+/// Each key is contained at most once in each of the sets.
+#[derive(Clone, Debug)]
+pub struct Frame<K> {
+  /// Keys that were pressed since the last frame.
+  pub(crate) pressed: Vec<K>,
+  /// Keys that are currently held down.
+  pub(crate) held: Vec<K>,
+  /// Keys that repeated since the last frame.
+  pub(crate) repeated: Vec<K>,
+  /// Keys that were released since the last frame.
+  pub(crate) released: Vec<K>,
+}
+
+impl<K> Frame<K>
+where
+  K: Eq,
+{
+  /// Clear all sets, retaining allocated memory.
+  pub(crate) fn clear(&mut self) {
+    let () = self.pressed.clear();
+    let () = self.held.clear();
+    let () = self.repeated.clear();
+    let () = self.released.clear();
+  }
+
+  /// Retrieve the keys that were pressed since the last frame.
+  #[inline]
+  pub fn pressed(&self) -> &[K] {
+    &self.pressed
+  }
+
+  /// Retrieve the keys that are currently held down, including those
+  /// [pressed][Frame::pressed] since the last frame.
+  #[inline]
+  pub fn held(&self) -> &[K] {
+    &self.held
+  }
+
+  /// Retrieve the keys that repeated since the last frame.
+  #[inline]
+  pub fn repeated(&self) -> &[K] {
+    &self.repeated
+  }
+
+  /// Retrieve the keys that were released since the last frame.
+  #[inline]
+  pub fn released(&self) -> &[K] {
+    &self.released
+  }
+
+  /// Check whether `key` was pressed since the last frame.
+  #[inline]
+  pub fn is_pressed(&self, key: &K) -> bool {
+    self.pressed.contains(key)
+  }
+
+  /// Check whether `key` is currently held down.
+  #[inline]
+  pub fn is_held(&self, key: &K) -> bool {
+    self.held.contains(key)
+  }
+
+  /// Check whether `key` repeated since the last frame.
+  #[inline]
+  pub fn is_repeated(&self, key: &K) -> bool {
+    self.repeated.contains(key)
+  }
+
+  /// Check whether `key` was released since the last frame.
+  #[inline]
+  pub fn is_released(&self, key: &K) -> bool {
+    self.released.contains(key)
+  }
+}
+
+impl<K> Default for Frame<K> {
+  fn default() -> Self {
+    Self {
+      pressed: Vec::new(),
+      held: Vec::new(),
+      repeated: Vec::new(),
+      released: Vec::new(),
+    }
+  }
+}
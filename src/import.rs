@@ -0,0 +1,403 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for importing key repeat settings of common desktop
+//! environments and compositors.
+
+use std::time::Duration;
+
+use crate::Config;
+use crate::RepeatMode;
+
+
+/// The GNOME schema containing keyboard settings.
+const GNOME_SCHEMA: &str = "org.gnome.desktop.peripherals.keyboard";
+/// The `dconf` path of [`GNOME_SCHEMA`], as used in section headers.
+const GNOME_PATH: &str = "org/gnome/desktop/peripherals/keyboard";
+
+
+/// Convert a repeat rate in Hz into the corresponding interval.
+fn rate_to_interval(rate: u32) -> Option<Duration> {
+  if rate == 0 {
+    None
+  } else {
+    Some(Duration::from_secs(1) / rate)
+  }
+}
+
+/// Strip a trailing `#` comment from `line`.
+fn strip_comment(line: &str) -> &str {
+  line
+    .split_once('#')
+    .map(|(line, _comment)| line)
+    .unwrap_or(line)
+}
+
+/// Find the numeric value following the token `name` in `tokens`.
+fn find_value<'t, T>(tokens: T, name: &str) -> Option<u32>
+where
+  T: IntoIterator<Item = &'t str>,
+{
+  let mut tokens = tokens.into_iter().skip_while(|token| *token != name);
+  let _name = tokens.next()?;
+  tokens.next()?.parse().ok()
+}
+
+/// Create a [`Config`] from the provided timeout and interval, falling
+/// back to the provided defaults for missing values.
+///
+/// If `enabled` is `Some(false)`, auto-repeat has been turned off and
+/// the resulting [`Config`] uses [`RepeatMode::Disabled`].
+fn config(
+  timeout: Option<Duration>,
+  interval: Option<Duration>,
+  enabled: Option<bool>,
+  defaults: (Duration, Duration),
+) -> Option<Config> {
+  if timeout.is_none() && interval.is_none() && enabled.is_none() {
+    return None
+  }
+
+  let timeout = timeout.unwrap_or(defaults.0);
+  let interval = interval.unwrap_or(defaults.1);
+  let mut config = Config::new(timeout, interval);
+  if enabled == Some(false) {
+    config.mode = RepeatMode::Disabled;
+  }
+  Some(config)
+}
+
+
+impl Config {
+  /// Create a [`Config`] from the output of `xset q`.
+  ///
+  /// The relevant part of the output looks along the lines of:
+  /// ```text
+  ///   auto repeat:  on    key click percent:  0    LED mask:  00000002
+  ///   auto repeat delay:  660    repeat rate:  25
+  /// ```
+  ///
+  /// If auto-repeat is turned off, the returned [`Config`] uses
+  /// [`RepeatMode::Disabled`]. `None` is returned if no repeat settings
+  /// were found.
+  pub fn from_xset(output: &str) -> Option<Self> {
+    let token = |label| {
+      let (_before, after) = output.split_once(label)?;
+      after.split_whitespace().next()
+    };
+    let value = |label| token(label)?.parse::<u32>().ok();
+
+    let enabled = token("auto repeat:").and_then(|state| match state {
+      "on" => Some(true),
+      "off" => Some(false),
+      _ => None,
+    });
+    let timeout = value("auto repeat delay:").map(|delay| Duration::from_millis(delay.into()));
+    let interval = value("repeat rate:").and_then(rate_to_interval);
+    config(
+      timeout,
+      interval,
+      enabled,
+      (Duration::from_millis(660), Duration::from_millis(40)),
+    )
+  }
+
+  /// Create a [`Config`] from a sway (or i3 compatible) configuration,
+  /// containing `repeat_delay` and `repeat_rate` settings.
+  ///
+  /// For example:
+  /// ```text
+  /// input type:keyboard {
+  ///   repeat_delay 300
+  ///   repeat_rate 30
+  /// }
+  /// ```
+  ///
+  /// A repeat rate of zero turns auto-repeat off, in which case the
+  /// returned [`Config`] uses [`RepeatMode::Disabled`]. `None` is
+  /// returned if no repeat settings were found.
+  pub fn from_sway(config_text: &str) -> Option<Self> {
+    let value = |name| {
+      config_text
+        .lines()
+        .find_map(|line| find_value(strip_comment(line).split_whitespace(), name))
+    };
+
+    let rate = value("repeat_rate");
+    let timeout = value("repeat_delay").map(|delay| Duration::from_millis(delay.into()));
+    let interval = rate.and_then(rate_to_interval);
+    config(
+      timeout,
+      interval,
+      rate.map(|rate| rate != 0),
+      (Duration::from_millis(600), Duration::from_millis(40)),
+    )
+  }
+
+  /// Create a [`Config`] from a Hyprland configuration, containing
+  /// `repeat_delay` and `repeat_rate` settings in an `input` block.
+  ///
+  /// For example:
+  /// ```text
+  /// input {
+  ///   repeat_rate = 30
+  ///   repeat_delay = 300
+  /// }
+  /// ```
+  ///
+  /// The `input:repeat_rate` and `input:repeat_delay` keyword syntax is
+  /// supported as well. A repeat rate of zero turns auto-repeat off, in
+  /// which case the returned [`Config`] uses [`RepeatMode::Disabled`].
+  /// `None` is returned if no repeat settings were found.
+  pub fn from_hyprland(config_text: &str) -> Option<Self> {
+    let mut blocks = Vec::new();
+    let mut delay = None;
+    let mut rate = None;
+
+    for line in config_text.lines() {
+      let line = strip_comment(line).trim();
+      if let Some(block) = line.strip_suffix('{') {
+        let () = blocks.push(block.trim());
+      } else if line == "}" {
+        let _block = blocks.pop();
+      } else if let Some((name, value)) = line.split_once('=') {
+        let name = match (blocks.as_slice(), name.trim()) {
+          (["input"], name) => name,
+          ([], name) => match name.strip_prefix("input:") {
+            Some(name) => name,
+            None => continue,
+          },
+          _ => continue,
+        };
+
+        let value = value.trim().parse::<u32>().ok();
+        match name {
+          "repeat_delay" => delay = value,
+          "repeat_rate" => rate = value,
+          _ => (),
+        }
+      }
+    }
+
+    let timeout = delay.map(|delay| Duration::from_millis(delay.into()));
+    let interval = rate.and_then(rate_to_interval);
+    config(
+      timeout,
+      interval,
+      rate.map(|rate| rate != 0),
+      (Duration::from_millis(600), Duration::from_millis(40)),
+    )
+  }
+
+  /// Create a [`Config`] from GNOME's keyboard settings, i.e., the
+  /// `repeat`, `delay`, and `repeat-interval` keys of the
+  /// `org.gnome.desktop.peripherals.keyboard` schema.
+  ///
+  /// Both the output of `dconf dump` and of `gsettings
+  /// list-recursively` are supported, for example:
+  /// ```text
+  /// [org/gnome/desktop/peripherals/keyboard]
+  /// delay=uint32 500
+  /// repeat-interval=uint32 30
+  /// ```
+  ///
+  /// Keys of other schemas are ignored. For `dconf dump` output, the
+  /// `[/]` section is considered as well, as produced when dumping the
+  /// schema's path directly. If auto-repeat is turned off, the returned
+  /// [`Config`] uses [`RepeatMode::Disabled`]. `None` is returned if no
+  /// repeat settings were found.
+  pub fn from_gnome(settings: &str) -> Option<Self> {
+    let mut section = None;
+    let mut enabled = None;
+    let mut delay = None;
+    let mut interval = None;
+
+    for line in settings.lines() {
+      let line = line.trim();
+      if let Some(name) = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+      {
+        section = Some(name);
+        continue
+      }
+
+      // `gsettings list-recursively` lines start with the schema, while
+      // `dconf dump` lines are grouped into sections.
+      let (name, value) = match line.strip_prefix(GNOME_SCHEMA) {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+          match rest.trim_start().split_once(char::is_whitespace) {
+            Some(setting) => setting,
+            None => continue,
+          }
+        },
+        _ if matches!(section, Some(GNOME_PATH | "/")) => match line.split_once('=') {
+          Some(setting) => setting,
+          None => continue,
+        },
+        _ => continue,
+      };
+
+      let value = value.trim();
+      let number = || {
+        value
+          .trim_start_matches("uint32")
+          .trim()
+          .parse::<u32>()
+          .ok()
+      };
+      match name.trim() {
+        "repeat" => enabled = value.parse::<bool>().ok(),
+        "delay" => delay = number(),
+        "repeat-interval" => interval = number(),
+        _ => (),
+      }
+    }
+
+    let timeout = delay.map(|delay| Duration::from_millis(delay.into()));
+    let interval = interval.map(|interval| Duration::from_millis(interval.into()));
+    config(
+      timeout,
+      interval,
+      enabled,
+      (Duration::from_millis(500), Duration::from_millis(30)),
+    )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MILLIS: Duration = Duration::from_millis(1);
+
+
+  /// Check that we can parse the output of `xset q`.
+  #[test]
+  fn xset_parsing() {
+    let output = r#"Keyboard Control:
+  auto repeat:  on    key click percent:  0    LED mask:  00000002
+  XKB indicators:
+    00: Caps Lock:   off    01: Num Lock:    on     02: Scroll Lock: off
+  auto repeat delay:  250    repeat rate:  50
+  auto repeating keys:  00ffffffdffffbbf
+"#;
+    let config = Config::from_xset(output).unwrap();
+    assert_eq!(config.timeout, 250 * MILLIS);
+    assert_eq!(config.interval, 20 * MILLIS);
+
+    assert_eq!(config.mode, RepeatMode::Synthesize);
+
+    let output = r#"Keyboard Control:
+  auto repeat:  off    key click percent:  0    LED mask:  00000002
+  auto repeat delay:  250    repeat rate:  50
+"#;
+    let config = Config::from_xset(output).unwrap();
+    assert_eq!(config.mode, RepeatMode::Disabled);
+
+    assert_eq!(Config::from_xset("Keyboard Control:"), None);
+  }
+
+
+  /// Check that we can parse sway configuration files.
+  #[test]
+  fn sway_parsing() {
+    let config_text = r#"
+# repeat_delay 100
+input type:keyboard {
+    xkb_layout us
+    repeat_delay 300
+    repeat_rate 25
+}
+"#;
+    let config = Config::from_sway(config_text).unwrap();
+    assert_eq!(config.timeout, 300 * MILLIS);
+    assert_eq!(config.interval, 40 * MILLIS);
+
+    let config = Config::from_sway("input * repeat_rate 50").unwrap();
+    assert_eq!(config.timeout, 600 * MILLIS);
+    assert_eq!(config.interval, 20 * MILLIS);
+
+    let config = Config::from_sway("input * repeat_rate 0").unwrap();
+    assert_eq!(config.mode, RepeatMode::Disabled);
+  }
+
+
+  /// Check that we can parse Hyprland configuration files.
+  #[test]
+  fn hyprland_parsing() {
+    let config_text = r#"
+device {
+    name = some-keyboard
+    repeat_rate = 10
+}
+
+input {
+    kb_layout = us
+    repeat_rate = 50 # fast
+    repeat_delay = 200
+
+    touchpad {
+        repeat_delay = 1000
+    }
+}
+"#;
+    let config = Config::from_hyprland(config_text).unwrap();
+    assert_eq!(config.timeout, 200 * MILLIS);
+    assert_eq!(config.interval, 20 * MILLIS);
+
+    let config = Config::from_hyprland("input:repeat_delay = 400").unwrap();
+    assert_eq!(config.timeout, 400 * MILLIS);
+    assert_eq!(config.interval, 40 * MILLIS);
+
+    let config = Config::from_hyprland("input:repeat_rate = 0").unwrap();
+    assert_eq!(config.mode, RepeatMode::Disabled);
+  }
+
+
+  /// Check that we can parse GNOME keyboard settings.
+  #[test]
+  fn gnome_parsing() {
+    let dump = r#"[org/gnome/desktop/peripherals/keyboard]
+delay=uint32 250
+numlock-state=true
+repeat-interval=uint32 15
+
+[org/gnome/desktop/peripherals/mouse]
+delay=uint32 1000
+"#;
+    let config = Config::from_gnome(dump).unwrap();
+    assert_eq!(config.timeout, 250 * MILLIS);
+    assert_eq!(config.interval, 15 * MILLIS);
+    assert_eq!(config.mode, RepeatMode::Synthesize);
+
+    let dump = r#"[/]
+repeat=false
+"#;
+    let config = Config::from_gnome(dump).unwrap();
+    assert_eq!(config.mode, RepeatMode::Disabled);
+
+    // Settings of other schemas are ignored.
+    let dump = r#"[org/gnome/desktop/a11y/keyboard]
+delay=uint32 100
+"#;
+    assert_eq!(Config::from_gnome(dump), None);
+
+    let list = r#"org.gnome.desktop.peripherals.keyboard delay uint32 350
+org.gnome.desktop.peripherals.keyboard repeat true
+org.gnome.desktop.peripherals.keyboard repeat-interval uint32 20
+"#;
+    let config = Config::from_gnome(list).unwrap();
+    assert_eq!(config.timeout, 350 * MILLIS);
+    assert_eq!(config.interval, 20 * MILLIS);
+    assert_eq!(config.mode, RepeatMode::Synthesize);
+
+    let list = r#"org.gnome.desktop.a11y.keyboard delay uint32 100
+org.gnome.desktop.peripherals.keyboard repeat false
+"#;
+    let config = Config::from_gnome(list).unwrap();
+    assert_eq!(config.timeout, 500 * MILLIS);
+    assert_eq!(config.mode, RepeatMode::Disabled);
+  }
+}
@@ -3,16 +3,37 @@
 
 //! Functionality for working with key repetitions.
 
-use std::collections::hash_map::Entry;
+use std::borrow::Borrow;
+use std::collections::vec_deque::Drain;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::BuildHasherDefault;
+use std::hash::DefaultHasher;
 use std::hash::Hash;
-use std::ops::Add;
-use std::ops::AddAssign;
+use std::mem::take;
 use std::ops::BitOrAssign;
-use std::ops::Sub;
 use std::time::Duration;
 use std::time::Instant;
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::anomaly::AnomalyLog;
+use crate::frame::push_unique;
+use crate::frame::Fire;
+use crate::state::KeyRecord;
+use crate::state::Snapshot;
+use crate::Anomaly;
+use crate::Config;
+use crate::Diagnostics;
+use crate::Frame;
+use crate::Queue;
+use crate::RepeatMode;
+use crate::State;
+use crate::Timestamp;
+
 
 /// Find the lesser of two `Option<Instant>` values.
 ///
@@ -33,10 +54,17 @@ where
 
 /// The state a single key can be in.
 #[derive(Clone, Copy, Debug)]
-enum KeyState<I> {
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub(crate) enum KeyState<I> {
   Pressed {
     pressed_at: I,
     fire_count: usize,
+    /// The delay of the first repeat beyond the configured timeout,
+    /// e.g., due to a time jump.
+    delay: Duration,
+    /// The number of repeats generated by the platform since the
+    /// press, which are pending delivery after it.
+    forwarded: usize,
   },
   Repeated {
     pressed_at: I,
@@ -47,22 +75,85 @@ enum KeyState<I> {
     pressed_at: I,
     fire_count: usize,
   },
+  /// The key is pressed, but auto-repeat got disabled for it.
+  Disabled {
+    pressed_at: I,
+  },
+  /// The key is pressed and repeats are generated by the platform and
+  /// forwarded by us.
+  Forwarded {
+    pressed_at: I,
+    fire_count: usize,
+  },
+}
+
+impl<I> KeyState<I> {
+  /// Convert all instants tracked using `f`.
+  fn map<J, F>(self, f: F) -> KeyState<J>
+  where
+    F: Fn(I) -> J,
+  {
+    match self {
+      Self::Pressed {
+        pressed_at,
+        fire_count,
+        delay,
+        forwarded,
+      } => KeyState::Pressed {
+        pressed_at: f(pressed_at),
+        fire_count,
+        delay,
+        forwarded,
+      },
+      Self::Repeated {
+        pressed_at,
+        next_repeat,
+        fire_count,
+      } => KeyState::Repeated {
+        pressed_at: f(pressed_at),
+        next_repeat: f(next_repeat),
+        fire_count,
+      },
+      Self::ReleasePending {
+        pressed_at,
+        fire_count,
+      } => KeyState::ReleasePending {
+        pressed_at: f(pressed_at),
+        fire_count,
+      },
+      Self::Disabled { pressed_at } => KeyState::Disabled {
+        pressed_at: f(pressed_at),
+      },
+      Self::Forwarded {
+        pressed_at,
+        fire_count,
+      } => KeyState::Forwarded {
+        pressed_at: f(pressed_at),
+        fire_count,
+      },
+    }
+  }
 }
 
 impl<I> KeyState<I>
 where
-  I: Copy + Ord + Add<Duration, Output = I> + AddAssign<Duration> + Sub<Output = Duration>,
+  I: Timestamp,
 {
   fn pressed(pressed_at: I) -> Self {
     Self::Pressed {
       pressed_at,
       fire_count: 0,
+      delay: Duration::ZERO,
+      forwarded: 0,
     }
   }
 
   fn on_press(&mut self, now: I) {
     match self {
-      Self::Pressed { .. } | Self::Repeated { .. } => {
+      Self::Pressed { .. }
+      | Self::Repeated { .. }
+      | Self::Disabled { .. }
+      | Self::Forwarded { .. } => {
         // If the key is already pressed we just got an AutoRepeat
         // event. We manage repetitions ourselves, so we skip any
         // handling.
@@ -74,18 +165,41 @@ where
         *self = Self::Pressed {
           pressed_at: now,
           fire_count: *fire_count,
+          delay: Duration::ZERO,
+          forwarded: 0,
         }
       },
     }
   }
 
-  fn on_release(&mut self, now: I, timeout: Duration, interval: Duration) {
+  fn on_release(
+    &mut self,
+    now: I,
+    timeout: Duration,
+    interval: Duration,
+    max_catch_up: Option<usize>,
+  ) {
     match self {
       Self::Pressed {
         pressed_at,
         fire_count,
+        forwarded,
+        ..
+      } if *forwarded > 0 => {
+        // The platform already generates repeats, so we don't
+        // synthesize any.
+        *self = Self::ReleasePending {
+          pressed_at: *pressed_at,
+          fire_count: fire_count.saturating_add(1).saturating_add(*forwarded),
+        }
+      },
+      Self::Pressed {
+        pressed_at,
+        fire_count,
+        delay,
+        ..
       } => {
-        let next_repeat = *pressed_at + timeout;
+        let next_repeat = *pressed_at + timeout + *delay;
         if now >= next_repeat {
           // We hit the auto-repeat "threshold".
           *self = Self::Repeated {
@@ -93,7 +207,7 @@ where
             next_repeat,
             fire_count: *fire_count + 1,
           };
-          let () = self.on_release(now, timeout, interval);
+          let () = self.on_release(now, timeout, interval, max_catch_up);
         } else {
           *self = Self::ReleasePending {
             pressed_at: *pressed_at,
@@ -110,13 +224,19 @@ where
         //     than `now`, because some versions of Rust may panic if
         //     this precondition is violated.
         let diff = now - (*next_repeat).min(now);
-        // TODO: Use `Duration::div_duration_f64` once stable.
-        *fire_count += (diff.as_secs_f64() / interval.as_secs_f64()).trunc() as usize;
+        // NB: Zero intervals are nonsensical, but we must not divide by
+        //     zero either way.
+        let fires = diff.as_nanos() / interval.as_nanos().max(1);
+        let mut fires = usize::try_from(fires).unwrap_or(usize::MAX);
         // If `now` is past the next auto repeat, take that into account
         // as well.
         if now > *next_repeat {
-          *fire_count += 1;
+          fires = fires.saturating_add(1);
+        }
+        if let Some(max_catch_up) = max_catch_up {
+          fires = fires.min(max_catch_up);
         }
+        *fire_count = fire_count.saturating_add(fires);
 
         *self = Self::ReleasePending {
           pressed_at: *pressed_at,
@@ -124,8 +244,191 @@ where
         }
       },
       Self::ReleasePending { .. } => {
-        debug_assert!(false, "released key was not pressed");
+        // The key had already been released. Callers are expected to
+        // filter out such duplicate releases, but either way there is
+        // nothing to be done.
+      },
+      Self::Disabled { pressed_at } => {
+        *self = Self::ReleasePending {
+          pressed_at: *pressed_at,
+          fire_count: 0,
+        }
+      },
+      Self::Forwarded {
+        pressed_at,
+        fire_count,
+      } => {
+        *self = Self::ReleasePending {
+          pressed_at: *pressed_at,
+          fire_count: *fire_count,
+        }
+      },
+    }
+  }
+
+  /// Handle a repeat generated by the platform, switching the key over
+  /// to forwarding such repeats.
+  fn on_repeat(&mut self) {
+    match self {
+      // The press itself has not been delivered yet. The repeat is to
+      // be delivered after it.
+      Self::Pressed { forwarded, .. } => {
+        *forwarded = forwarded.saturating_add(1);
+      },
+      Self::Forwarded { fire_count, .. } => {
+        *fire_count = fire_count.saturating_add(1);
+      },
+      Self::Repeated {
+        pressed_at,
+        fire_count,
+        ..
+      } => {
+        *self = Self::Forwarded {
+          pressed_at: *pressed_at,
+          fire_count: fire_count.saturating_add(1),
+        }
+      },
+      Self::ReleasePending { .. } | Self::Disabled { .. } => (),
+    }
+  }
+
+  /// Stop synthesizing repeats for the key, if it is currently
+  /// repeating.
+  fn forward(&mut self) {
+    if let Self::Repeated {
+      pressed_at,
+      fire_count,
+      ..
+    } = self
+    {
+      *self = Self::Forwarded {
+        pressed_at: *pressed_at,
+        fire_count: *fire_count,
+      }
+    }
+  }
+
+  fn pressed_at(&self) -> I {
+    match self {
+      Self::Pressed { pressed_at, .. }
+      | Self::Repeated { pressed_at, .. }
+      | Self::ReleasePending { pressed_at, .. }
+      | Self::Disabled { pressed_at }
+      | Self::Forwarded { pressed_at, .. } => *pressed_at,
+    }
+  }
+
+  /// Shift the repeat schedule by `by`.
+  ///
+  /// The instant at which the key got pressed is left untouched.
+  fn shift(&mut self, by: Duration) {
+    match self {
+      Self::Pressed { delay, .. } => {
+        *delay += by;
+      },
+      Self::Repeated { next_repeat, .. } => {
+        *next_repeat += by;
+      },
+      Self::ReleasePending { .. } | Self::Disabled { .. } | Self::Forwarded { .. } => (),
+    }
+  }
+
+  /// Skip synthesized repeats due by `now` beyond the first
+  /// `max_catch_up` ones.
+  fn catch_up(&mut self, now: I, interval: Duration, max_catch_up: usize) {
+    if let Self::Repeated { next_repeat, .. } = self {
+      if *next_repeat <= now {
+        let interval = interval.as_nanos().max(1);
+        let due = (now - *next_repeat).as_nanos() / interval + 1;
+        let skip = due.saturating_sub(max_catch_up as u128);
+        if skip > 0 {
+          let nanos = skip.saturating_mul(interval);
+          let secs = u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX);
+          // The remainder is always less than a second.
+          let nanos = (nanos % 1_000_000_000) as u32;
+          *next_repeat += Duration::new(secs, nanos);
+        }
+      }
+    }
+  }
+
+  /// Count the number of fires due by `now`, assuming auto-repeat
+  /// stays enabled.
+  ///
+  /// If `platform_repeats` is `true`, repeats stop being synthesized
+  /// after the next fire.
+  fn pending_fires(
+    &self,
+    now: I,
+    timeout: Duration,
+    interval: Duration,
+    max_catch_up: Option<usize>,
+    platform_repeats: bool,
+  ) -> usize {
+    // Count the synthesized repeats due by `now`, the first of which is
+    // due at `next_repeat`.
+    let repeats = |next_repeat: I| {
+      if next_repeat > now {
+        return 0
+      }
+      let due = (now - next_repeat).as_nanos() / interval.as_nanos().max(1);
+      let due = usize::try_from(due).unwrap_or(usize::MAX).saturating_add(1);
+      max_catch_up.map_or(due, |max_catch_up| due.min(max_catch_up))
+    };
+
+    match *self {
+      Self::Pressed {
+        pressed_at,
+        fire_count,
+        delay,
+        forwarded,
+      } => {
+        if pressed_at > now {
+          return 0
+        }
+        let repeats = if forwarded > 0 {
+          forwarded
+        } else if platform_repeats {
+          0
+        } else {
+          repeats(pressed_at + timeout + delay)
+        };
+        fire_count.saturating_add(1).saturating_add(repeats)
+      },
+      Self::Repeated {
+        pressed_at,
+        next_repeat,
+        fire_count,
+      } => {
+        if fire_count > 0 {
+          if pressed_at > now {
+            0
+          } else if platform_repeats {
+            fire_count
+          } else {
+            fire_count.saturating_add(repeats(next_repeat))
+          }
+        } else if platform_repeats {
+          repeats(next_repeat).min(1)
+        } else {
+          repeats(next_repeat)
+        }
+      },
+      Self::ReleasePending {
+        pressed_at,
+        fire_count,
+      }
+      | Self::Forwarded {
+        pressed_at,
+        fire_count,
+      } => {
+        if pressed_at > now {
+          0
+        } else {
+          fire_count
+        }
       },
+      Self::Disabled { .. } => 0,
     }
   }
 
@@ -146,6 +449,10 @@ where
       Self::ReleasePending {
         pressed_at,
         fire_count,
+      }
+      | Self::Forwarded {
+        pressed_at,
+        fire_count,
       } => {
         if *fire_count > 0 {
           Some(*pressed_at)
@@ -153,6 +460,7 @@ where
           None
         }
       },
+      Self::Disabled { .. } => None,
     }
   }
 
@@ -164,13 +472,20 @@ where
       Self::Pressed {
         pressed_at,
         fire_count,
+        delay,
+        forwarded,
       } => {
         if let Some(count) = fire_count.checked_sub(1) {
           *fire_count = count;
+        } else if *forwarded > 0 {
+          *self = KeyState::Forwarded {
+            pressed_at: *pressed_at,
+            fire_count: *forwarded,
+          };
         } else {
           *self = KeyState::Repeated {
             pressed_at: *pressed_at,
-            next_repeat: *pressed_at + timeout,
+            next_repeat: *pressed_at + timeout + *delay,
             fire_count: 0,
           };
         }
@@ -186,22 +501,344 @@ where
           *next_repeat += interval;
         }
       },
-      Self::ReleasePending { fire_count, .. } => {
+      Self::ReleasePending { fire_count, .. } | Self::Forwarded { fire_count, .. } => {
         *fire_count = fire_count.saturating_sub(1);
       },
+      Self::Disabled { .. } => (),
+    }
+  }
+
+  /// Restart the auto-repeat timeout as of `now`, dropping any
+  /// pending repeats.
+  fn restart(&mut self, now: I, timeout: Duration) {
+    *self = Self::Repeated {
+      pressed_at: self.pressed_at(),
+      next_repeat: now + timeout,
+      fire_count: 0,
+    }
+  }
+
+  /// Postpone the next synthesized repeat by `by`.
+  fn postpone(&mut self, by: Duration) {
+    if let Self::Repeated { next_repeat, .. } = self {
+      *next_repeat += by;
+    }
+  }
+
+  /// Determine the [`Phase`] the key is in.
+  fn phase(&self) -> Phase {
+    match self {
+      Self::Pressed { .. } => Phase::Pressed,
+      Self::Repeated { .. } | Self::Forwarded { .. } => Phase::Repeating,
+      Self::Disabled { .. } => Phase::Disabled,
+      Self::ReleasePending { .. } => Phase::Released,
+    }
+  }
+
+  /// Check whether the next fire corresponds to a fresh press of the
+  /// key, as opposed to a repeat.
+  fn is_fresh(&self) -> bool {
+    matches!(self, Self::Pressed { fire_count: 0, .. })
+  }
+
+  /// Check whether the key has been released.
+  fn is_released(&self) -> bool {
+    matches!(self, Self::ReleasePending { .. })
+  }
+
+  /// Disable auto-repeat for the key, dropping any pending repeats.
+  fn disable(&mut self) {
+    *self = Self::Disabled {
+      pressed_at: self.pressed_at(),
     }
   }
 }
 
 
-/// An enum representing the two possible auto-key-repeat states
-/// supported.
-#[derive(Debug)]
+/// The kinds of key events we handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum KeyEvent {
+  Press,
+  Repeat,
+  Release,
+}
+
+
+/// An enum representing the ways in which a handler can influence the
+/// auto-repeat of a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum KeyRepeat {
   /// Auto-key-repeat is enabled.
   Enabled,
   /// Auto-key-repeat is disabled.
   Disabled,
+  /// Restart the auto-repeat timeout as of the current "tick", as if
+  /// the key had been freshly pressed.
+  ///
+  /// Interval and limit set earlier stay in effect.
+  Restart,
+  /// Restart the auto-repeat timeout as of the current "tick", using
+  /// the provided timeout instead of the configured one.
+  Timeout(Duration),
+  /// Postpone the next repeat by the provided duration.
+  Postpone(Duration),
+  /// Limit the number of remaining repeats to the provided count,
+  /// after which auto-repeat is disabled.
+  Limit(usize),
+  /// Switch the key over to the provided auto-repeat interval.
+  Interval(Duration),
+}
+
+
+/// The phase a tracked key is in, as reported by [`Keys::phase`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+  /// The key is held down, but the auto-repeat timeout has not passed
+  /// yet.
+  Pressed,
+  /// The key is held down and auto-repeating.
+  Repeating,
+  /// The key is held down, but auto-repeat has been disabled for it.
+  Disabled,
+  /// The key has been released, but not all of its presses and
+  /// repeats have been delivered yet.
+  Released,
+  /// The key got suspended by a context switch and has not been
+  /// released yet.
+  Suspended,
+}
+
+
+/// The map type used for tracking pressed keys.
+///
+/// We use a deterministic hasher, so that iteration order (and, hence,
+/// the order in which keys are reported) only depends on the sequence
+/// of operations performed and not on random state. That is required
+/// for reproducibility, e.g., when rewinding to a
+/// [`Snapshot`].
+pub(crate) type KeyMap<K, I, D> = HashMap<K, KeyEntry<I, D>, BuildHasherDefault<DefaultHasher>>;
+
+
+/// Per-key state influenced by the handler.
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub(crate) struct KeyControl {
+  /// Whether the initial press of the key has been delivered.
+  delivered: bool,
+  /// Whether a repeat has been delivered since the key got pressed or
+  /// the timeout restarted.
+  repeating: bool,
+  /// The auto-repeat interval to use instead of the configured one, if
+  /// any.
+  interval: Option<Duration>,
+  /// The number of repeats remaining before auto-repeat is disabled,
+  /// if limited.
+  remaining: Option<usize>,
+  /// Whether the key got suspended by a context switch.
+  suspended: bool,
+}
+
+
+/// The state of a key that is currently tracked, along with the devices
+/// holding it down.
+#[derive(Clone, Debug)]
+pub(crate) struct KeyEntry<I, D> {
+  /// The key's repetition state.
+  state: KeyState<I>,
+  /// The devices currently reporting the key as pressed.
+  devices: Vec<D>,
+  /// The instant at which the key got force-released because it was
+  /// considered stuck, if any.
+  stuck_at: Option<I>,
+  /// State influenced by the handler.
+  control: KeyControl,
+}
+
+impl<I, D> KeyEntry<I, D>
+where
+  I: Timestamp,
+  D: Eq,
+{
+  /// Press the key on behalf of `device`.
+  ///
+  /// Returns `false` if `device` was already holding the key.
+  fn on_press(&mut self, now: I, device: D) -> bool {
+    let new = !self.devices.contains(&device);
+    if new {
+      let () = self.devices.push(device);
+    }
+    if self.state.is_released() {
+      // A key pressed again is subject to the configured auto-repeat
+      // behavior again.
+      self.control = KeyControl {
+        delivered: self.control.delivered,
+        ..KeyControl::default()
+      };
+    }
+    let () = self.state.on_press(now);
+    new
+  }
+
+  /// Retrieve the auto-repeat interval in effect for the key, given
+  /// the configured `interval`.
+  #[inline]
+  fn interval(&self, interval: Duration) -> Duration {
+    self.control.interval.unwrap_or(interval)
+  }
+
+  /// Determine the kind of the next fire.
+  fn next_fire(&self) -> Fire {
+    if !self.control.delivered || self.state.is_fresh() {
+      Fire::Press
+    } else {
+      Fire::Repeat
+    }
+  }
+
+  /// Take note of `fire` having been delivered.
+  fn on_fire(&mut self, fire: Fire) {
+    self.control.delivered = true;
+    if fire == Fire::Repeat {
+      self.control.repeating = true;
+      if let Some(remaining) = &mut self.control.remaining {
+        *remaining = remaining.saturating_sub(1);
+      }
+    }
+  }
+
+  /// Check whether the key has exhausted its limit of repeats.
+  #[inline]
+  fn is_exhausted(&self) -> bool {
+    self.control.remaining == Some(0)
+  }
+
+  /// Check whether the key is held down and not suspended.
+  #[inline]
+  fn is_held(&self) -> bool {
+    !self.devices.is_empty() && !self.control.suspended
+  }
+
+  /// Suspend the key, so that it no longer registers until it got
+  /// pressed again.
+  ///
+  /// Returns `false` if the entry is no longer of any relevance,
+  /// because the key is not held down.
+  fn suspend(&mut self) -> bool {
+    if self.devices.is_empty() {
+      return false
+    }
+    let () = self.state.disable();
+    self.control.suspended = true;
+    true
+  }
+
+  /// Determine the [`Phase`] the key is in.
+  fn phase(&self) -> Phase {
+    if self.control.suspended {
+      return Phase::Suspended
+    }
+
+    match self.state {
+      // Until the first repeat got delivered we are still waiting for
+      // the timeout to pass.
+      KeyState::Repeated { fire_count: 0, .. } if !self.control.repeating => Phase::Pressed,
+      state => state.phase(),
+    }
+  }
+
+  /// Release the key on behalf of `device`.
+  ///
+  /// Returns `false` if `device` was not holding the key.
+  fn on_release(&mut self, now: I, device: &D, config: &Config) -> bool {
+    if let Some(idx) = self.devices.iter().position(|d| d == device) {
+      let _device = self.devices.swap_remove(idx);
+      // Only once the last device let go of the key do we consider it
+      // released.
+      if self.devices.is_empty() {
+        let () = self.state.on_release(
+          now,
+          config.timeout,
+          self.interval(config.interval),
+          config.max_catch_up,
+        );
+      }
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Release the key on behalf of all devices holding it.
+  fn release_all(&mut self, now: I, config: &Config) {
+    if !self.devices.is_empty() {
+      let () = self.devices.clear();
+      let () = self.state.on_release(
+        now,
+        config.timeout,
+        self.interval(config.interval),
+        config.max_catch_up,
+      );
+    }
+  }
+
+  /// Retrieve the instant at which the key is to be considered stuck,
+  /// if it is still held down.
+  fn stuck_deadline(&self, max_hold: Option<Duration>) -> Option<I> {
+    if self.devices.is_empty() {
+      None
+    } else {
+      max_hold.map(|max_hold| self.state.pressed_at() + max_hold)
+    }
+  }
+
+  /// Count the number of presses and repeats a "tick" at `now` would
+  /// deliver, assuming auto-repeat stays enabled.
+  fn pending_fires(&self, now: I, config: &Config, platform_repeats: bool) -> usize {
+    let interval = self.interval(config.interval);
+    let mut state = self.state;
+    if let Some(deadline) = self.stuck_deadline(config.max_hold) {
+      if deadline <= now {
+        let () = state.on_release(deadline, config.timeout, interval, config.max_catch_up);
+      }
+    }
+
+    let count = state.pending_fires(
+      now,
+      config.timeout,
+      interval,
+      config.max_catch_up,
+      platform_repeats,
+    );
+    if config.mode == RepeatMode::Disabled {
+      return count.min(1)
+    }
+
+    match self.control.remaining {
+      // Presses do not count towards the limit, but at least the fire
+      // exhausting it is delivered.
+      Some(remaining) => {
+        let presses = usize::from(self.next_fire() == Fire::Press);
+        count.min(remaining.saturating_add(presses).max(1))
+      },
+      None => count,
+    }
+  }
+
+  /// Remove `device` from the set of devices holding the key, without
+  /// releasing it.
+  ///
+  /// Returns `true` if the entry is no longer of any relevance, because
+  /// `device` was the last one holding the key.
+  fn forget(&mut self, device: &D) -> bool {
+    if let Some(idx) = self.devices.iter().position(|d| d == device) {
+      let _device = self.devices.swap_remove(idx);
+      self.devices.is_empty()
+    } else {
+      false
+    }
+  }
 }
 
 
@@ -216,122 +853,816 @@ pub enum KeyRepeat {
 /// function for all the key presses and repeats accumulated since the
 /// last time it was invoked.
 ///
+/// Key presses can be attributed to a device (of type `D`), e.g., when
+/// multiple keyboards are attached. A key is only considered released
+/// once every device that pressed it has released it again. Use the
+/// [`on_device_key_press`][Keys::on_device_key_press] and
+/// [`on_device_key_release`][Keys::on_device_key_release] methods for
+/// that purpose. If there is no need to distinguish between devices,
+/// the default `D = ()` can be used with the device-agnostic methods.
+///
 /// For a complete and runnable example illustrating usage please refer
 /// to [`winit-phys-events.rs`][winit-phys-events].
 ///
 /// [winit-phys-events]: https://github.com/d-e-s-o/keypeat/blob/main/examples/winit-phys-events.rs
-#[derive(Debug)]
-pub struct Keys<K, I = Instant> {
-  /// The "timeout" after the initial key press after which the first
-  /// repeat is issued.
-  timeout: Duration,
-  /// The interval for any subsequent repeats.
-  interval: Duration,
+#[derive(Clone, Debug)]
+pub struct Keys<K, I = Instant, D = ()> {
+  /// The configuration in use.
+  config: Config,
   /// A map from keys that are currently pressed to internally used
   /// key repetition state.
-  pressed: HashMap<K, KeyState<I>>,
+  pressed: KeyMap<K, I, D>,
+  /// Anomalies encountered and not yet retrieved by the user.
+  anomalies: AnomalyLog<K, I>,
+  /// The latest instant observed so far.
+  last_time: Option<I>,
+  /// The instant of the last "tick".
+  last_tick: Option<I>,
+  /// Whether the platform has been observed to generate repeats.
+  platform_repeats: bool,
+  /// The depth of the input context stack.
+  context: usize,
+  /// The key state of the last frame, as per [`Keys::frame`].
+  frame: Frame<K>,
+  /// Events queued by the handler, as per [`Keys::tick_queued`].
+  queue: Queue<K, I, D>,
 }
 
-impl<K, I> Keys<K, I>
+impl<K, I, D> Keys<K, I, D>
 where
   K: Eq + Hash,
-  I: Copy + Ord + Add<Duration, Output = I> + AddAssign<Duration> + Sub<Output = Duration>,
+  D: Eq,
+  I: Timestamp,
 {
   /// Create a new [`Keys`] object using `timeout` as the initial
   /// timeout after which pressed keys transition into auto-repeat mode
   /// at interval `interval`.
   pub fn new(timeout: Duration, interval: Duration) -> Self {
-    Self {
-      timeout,
-      interval,
-      pressed: HashMap::new(),
-    }
+    Self::with_config(Config::new(timeout, interval))
   }
 
-  fn on_key_event(&mut self, now: I, key: K, pressed: bool) {
-    match pressed {
-      false => match self.pressed.entry(key) {
-        Entry::Vacant(_vacancy) => {
-          // Note that a key could be released without being marked here
-          // as pressed anymore, if auto repeat had been disabled. In
-          // such a case it is fine to just ignore the release.
-        },
-        Entry::Occupied(mut occupancy) => {
-          let state = occupancy.get_mut();
-          let () = state.on_release(now, self.timeout, self.interval);
-        },
-      },
-      true => match self.pressed.entry(key) {
-        Entry::Vacant(vacancy) => {
-          let _state = vacancy.insert(KeyState::pressed(now));
-        },
-        Entry::Occupied(mut occupancy) => {
-          let state = occupancy.get_mut();
-          let () = state.on_press(now);
-        },
-      },
+  /// Create a new [`Keys`] object using the provided configuration.
+  pub fn with_config(config: Config) -> Self {
+    Self {
+      config,
+      pressed: KeyMap::default(),
+      anomalies: AnomalyLog::new(),
+      last_time: None,
+      last_tick: None,
+      platform_repeats: false,
+      context: 0,
+      frame: Frame::default(),
+      queue: Queue::default(),
     }
   }
 
-  /// This method is to be invoked on every key press received.
-  pub fn on_key_press(&mut self, now: I, key: K) {
-    self.on_key_event(now, key, true)
+  /// Retrieve the configuration in use.
+  #[inline]
+  pub fn config(&self) -> &Config {
+    &self.config
   }
 
-  /// This method is to be invoked on every key release received.
-  pub fn on_key_release(&mut self, now: I, key: K) {
-    self.on_key_event(now, key, false)
+  /// Take note of `now` as the current time, checking for time jumps.
+  fn observe_time(&mut self, now: I) {
+    if let Some(last) = self.last_time {
+      if now <= last {
+        return
+      }
+
+      if let Some(max_gap) = self.config.max_gap {
+        let gap = now - last;
+        if gap > max_gap {
+          // Pretend that no time passed between `last` and `now`, so
+          // that we neither deliver an excessive amount of catch-up
+          // repeats nor have to calculate them.
+          let () = self
+            .pressed
+            .values_mut()
+            .for_each(|entry| entry.state.shift(gap));
+          let () = self.anomalies.record(Anomaly::TimeJump {
+            from: last,
+            to: now,
+          });
+        }
+      }
+    }
+    self.last_time = Some(now);
   }
 
-  /// Handle a "tick", i.e., evaluate currently pressed keys based on
-  /// the provided time, invoking `handler` for each overdue repeat
-  /// event.
+  /// Adjust `now` so that it is not earlier than the last "tick".
   ///
-  /// `handler` can change the key's [`KeyRepeat`] state (key repetition
-  /// is enabled by default).
-  ///
-  /// Furthermore, `handler` may return any kind of state that can be
-  /// bitwise ORed, allowing to communicate an abstract notion of
-  /// "changes triggered" to callers. In addition, the instant at which
-  /// the next "tick" is likely to occur (and, hence, this function
-  /// should be invoked) is returned as well (if any).
-  // TODO: It could be beneficial to coalesce nearby ticks into a single
-  //       one, to reduce the number of event loop wake ups.
-  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
-  where
+  /// Events predating the last tick cannot be accounted for correctly
+  /// anymore, as repeats may already have been delivered. We treat them
+  /// as if they happened at the time of said tick instead.
+  fn adjust_time(&mut self, key: &K, now: I) -> I {
+    match self.last_tick {
+      Some(last_tick) if now < last_tick => {
+        let () = self.anomalies.record_out_of_order(key, now, last_tick);
+        last_tick
+      },
+      _ => now,
+    }
+  }
+
+  fn on_key_event(&mut self, now: I, device: D, key: K, event: KeyEvent) {
+    let () = self.observe_time(now);
+    let now = self.adjust_time(&key, now);
+
+    match event {
+      KeyEvent::Release => {
+        if let Some(entry) = self.pressed.get_mut(&key) {
+          // A release can't happen before the corresponding press. If
+          // it appears to, we treat it as happening at the time of the
+          // press.
+          let pressed_at = entry.state.pressed_at();
+          let now = if now < pressed_at {
+            let () = self.anomalies.record_out_of_order(&key, now, pressed_at);
+            pressed_at
+          } else {
+            now
+          };
+
+          let released = entry.on_release(now, &device, &self.config);
+          if !released {
+            let () = self
+              .anomalies
+              .record_input(Anomaly::DuplicateRelease { key, at: now });
+          }
+        } else {
+          let () = self
+            .anomalies
+            .record_input(Anomaly::UnknownRelease { key, at: now });
+        }
+      },
+      KeyEvent::Repeat if self.is_pressed_by(&key, &device) => {
+        if self.config.mode == RepeatMode::Hybrid {
+          self.platform_repeats = true;
+          if let Some(entry) = self.pressed.get_mut(&key) {
+            let () = entry.state.on_repeat();
+          }
+        }
+      },
+      KeyEvent::Press | KeyEvent::Repeat => {
+        if let Some(entry) = self.pressed.get_mut(&key) {
+          let pressed = entry.on_press(now, device);
+          if !pressed {
+            let () = self
+              .anomalies
+              .record_input(Anomaly::DuplicatePress { key, at: now });
+          }
+        } else {
+          let _entry = self.pressed.insert(
+            key,
+            KeyEntry {
+              state: KeyState::pressed(now),
+              devices: vec![device],
+              stuck_at: None,
+              control: KeyControl::default(),
+            },
+          );
+        }
+      },
+    }
+  }
+
+  /// Check whether `key` is currently held down by `device`.
+  pub(crate) fn is_pressed_by(&self, key: &K, device: &D) -> bool {
+    self
+      .pressed
+      .get(key)
+      .map(|entry| entry.devices.contains(device))
+      .unwrap_or(false)
+  }
+
+  /// This method is to be invoked on every key press received.
+  ///
+  /// The press is attributed to the default device.
+  pub fn on_key_press(&mut self, now: I, key: K)
+  where
+    D: Default,
+  {
+    self.on_key_event(now, D::default(), key, KeyEvent::Press)
+  }
+
+  /// This method is to be invoked on every auto-repeat generated by the
+  /// platform and received in the form of an additional key press.
+  ///
+  /// The repeat is attributed to the default device.
+  ///
+  /// Depending on the configured [`RepeatMode`], such repeats are
+  /// either ignored or forwarded. A repeat of a key that is not known
+  /// to be pressed is treated as a press.
+  pub fn on_key_repeat(&mut self, now: I, key: K)
+  where
+    D: Default,
+  {
+    self.on_key_event(now, D::default(), key, KeyEvent::Repeat)
+  }
+
+  /// This method is to be invoked on every key release received.
+  ///
+  /// The release is attributed to the default device.
+  pub fn on_key_release(&mut self, now: I, key: K)
+  where
+    D: Default,
+  {
+    self.on_key_event(now, D::default(), key, KeyEvent::Release)
+  }
+
+  /// This method is to be invoked on every key press received from
+  /// `device`.
+  ///
+  /// Presses of a key that is already held down by another device are
+  /// tracked, but do not otherwise affect the key's repetition.
+  pub fn on_device_key_press(&mut self, now: I, device: D, key: K) {
+    self.on_key_event(now, device, key, KeyEvent::Press)
+  }
+
+  /// This method is to be invoked on every auto-repeat generated by the
+  /// platform for a key held by `device`.
+  ///
+  /// Please refer to [`on_key_repeat`][Keys::on_key_repeat] for
+  /// details.
+  pub fn on_device_key_repeat(&mut self, now: I, device: D, key: K) {
+    self.on_key_event(now, device, key, KeyEvent::Repeat)
+  }
+
+  /// This method is to be invoked on every key release received from
+  /// `device`.
+  ///
+  /// The key is only considered released once every device that
+  /// pressed it has released it.
+  pub fn on_device_key_release(&mut self, now: I, device: D, key: K) {
+    self.on_key_event(now, device, key, KeyEvent::Release)
+  }
+
+  /// Release all keys held by `device`, e.g., because it got
+  /// disconnected.
+  ///
+  /// Keys that are also held by other devices stay pressed.
+  pub fn release_device(&mut self, now: I, device: &D) {
+    let () = self.observe_time(now);
+    let () = self.pressed.values_mut().for_each(|entry| {
+      let _released = entry.on_release(now, device, &self.config);
+    });
+  }
+
+  /// Release all keys, e.g., because the window lost focus and release
+  /// events may no longer be received.
+  ///
+  /// Compared to [`clear`][Keys::clear], any presses and repeats that
+  /// accumulated up to `now` are still delivered on the next
+  /// [`tick`][Keys::tick].
+  pub fn release_all(&mut self, now: I) {
+    let () = self.observe_time(now);
+    let () = self
+      .pressed
+      .values_mut()
+      .for_each(|entry| entry.release_all(now, &self.config));
+  }
+
+  /// Forget about all keys held by `device`, without delivering any
+  /// pending presses or repeats for them.
+  ///
+  /// Compared to [`release_device`][Keys::release_device], this method
+  /// silently drops the state of all keys only held by `device`. Keys
+  /// that are also held by other devices stay pressed.
+  pub fn forget_device(&mut self, device: &D) {
+    let () = self.pressed.retain(|_key, entry| !entry.forget(device));
+  }
+
+  /// Check whether `key` is currently held down.
+  pub fn is_pressed<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self
+      .pressed
+      .get(key)
+      .map(KeyEntry::is_held)
+      .unwrap_or(false)
+  }
+
+  /// Retrieve the instant at which `key` got pressed, if it is
+  /// currently held down.
+  pub fn pressed_at<Q>(&self, key: &Q) -> Option<I>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self
+      .pressed
+      .get(key)
+      .filter(|entry| entry.is_held())
+      .map(|entry| entry.state.pressed_at())
+  }
+
+  /// Retrieve the duration for which `key` has been held down as of
+  /// `now`, if it is currently held down.
+  pub fn held_for<Q>(&self, key: &Q, now: I) -> Option<Duration>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self
+      .pressed_at(key)
+      .map(|pressed_at| now - pressed_at.min(now))
+  }
+
+  /// Retrieve the [`Phase`] `key` is in, if it is being tracked.
+  ///
+  /// Keys are tracked while held down and, after having been
+  /// released, until all of their presses and repeats have been
+  /// delivered.
+  pub fn phase<Q>(&self, key: &Q) -> Option<Phase>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.pressed.get(key).map(KeyEntry::phase)
+  }
+
+  /// Count the number of presses and repeats of `key` that a
+  /// [`tick`][Keys::tick] at `now` would deliver, assuming the handler
+  /// does not disable auto-repeat.
+  pub fn pending_fires<Q>(&self, key: &Q, now: I) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self
+      .pressed
+      .get(key)
+      .map(|entry| entry.pending_fires(now, &self.config, self.platform_repeats))
+      .unwrap_or(0)
+  }
+
+  /// Iterate over all keys currently held down, in no particular
+  /// order.
+  pub fn iter_pressed(&self) -> impl Iterator<Item = &K> {
+    self
+      .pressed
+      .iter()
+      .filter(|(_key, entry)| entry.is_held())
+      .map(|(key, _entry)| key)
+  }
+
+  /// Push a new input context, e.g., because a menu got opened.
+  ///
+  /// All keys held down are suspended: they stop repeating, their
+  /// eventual release is swallowed, and they only register again once
+  /// pressed anew. Presses and repeats not yet delivered are dropped,
+  /// so it may be advisable to [`tick`][Keys::tick] before switching
+  /// contexts.
+  pub fn push_context(&mut self) {
+    let () = self.suspend();
+    self.context += 1;
+  }
+
+  /// Pop the current input context, returning to the previous one.
+  ///
+  /// Keys held down are suspended in the same way as when
+  /// [pushing][Keys::push_context] a context, including those held
+  /// since before the context was pushed. Returns `false` if there is
+  /// no context to pop.
+  pub fn pop_context(&mut self) -> bool {
+    if self.context == 0 {
+      return false
+    }
+    let () = self.suspend();
+    self.context -= 1;
+    true
+  }
+
+  /// Retrieve the depth of the input context stack, i.e., the number
+  /// of contexts pushed and not yet popped.
+  #[inline]
+  pub fn context(&self) -> usize {
+    self.context
+  }
+
+  /// Suspend all keys held down.
+  fn suspend(&mut self) {
+    let () = self.pressed.retain(|_key, entry| entry.suspend());
+  }
+
+  /// Handle a "tick", i.e., evaluate currently pressed keys based on
+  /// the provided time, invoking `handler` for each overdue repeat
+  /// event.
+  ///
+  /// `handler` can change the key's [`KeyRepeat`] state (key repetition
+  /// is enabled by default).
+  ///
+  /// Furthermore, `handler` may return any kind of state that can be
+  /// bitwise ORed, allowing to communicate an abstract notion of
+  /// "changes triggered" to callers. In addition, the instant at which
+  /// the next "tick" is likely to occur (and, hence, this function
+  /// should be invoked) is returned as well (if any).
+  // TODO: It could be beneficial to coalesce nearby ticks into a single
+  //       one, to reduce the number of event loop wake ups.
+  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
     F: FnMut(&K, &mut KeyRepeat) -> C,
     C: Default + BitOrAssign,
   {
+    let result = self.tick_with(
+      now,
+      |key, _fire, repeat| Ok::<_, Infallible>(handler(key, repeat)),
+      |_key| (),
+    );
+    match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    }
+  }
+
+  /// Handle a "tick" with a fallible `handler`.
+  ///
+  /// This method behaves like [`tick`][Keys::tick], except that
+  /// processing stops as soon as `handler` reports an error, which is
+  /// then returned. The press or repeat for which the error was
+  /// reported, along with all that were not yet handled, stay pending
+  /// and are delivered on the next "tick". Changes reported by
+  /// `handler` before the error occurred are lost.
+  pub fn try_tick<F, C, E>(&mut self, now: I, mut handler: F) -> Result<(C, Option<I>), E>
+  where
+    F: FnMut(&K, &mut KeyRepeat) -> Result<C, E>,
+    C: Default + BitOrAssign,
+  {
+    self.tick_with(now, |key, _fire, repeat| handler(key, repeat), |_key| ())
+  }
+
+  /// Handle a "tick", providing `handler` with a [`Queue`] for
+  /// injecting synthetic key events.
+  ///
+  /// This method behaves like [`tick`][Keys::tick], except that events
+  /// queued by `handler` are applied after all presses and repeats
+  /// have been handled, in the order in which they were queued. The
+  /// returned instant takes queued events into account.
+  pub fn tick_queued<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(&K, &mut KeyRepeat, &mut Queue<K, I, D>) -> C,
+    C: Default + BitOrAssign,
+  {
+    let mut queue = take(&mut self.queue);
+    let result = self.tick_with(
+      now,
+      |key, _fire, repeat| Ok::<_, Infallible>(handler(key, repeat, &mut queue)),
+      |_key| (),
+    );
+    let (change, mut next_tick) = match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    };
+
+    for queued in queue.drain() {
+      // Any press or repeat resulting from the event can only be due
+      // once the event happened.
+      next_tick = min_instant(next_tick, Some(queued.at.max(now)));
+      let () = self.on_key_event(queued.at, queued.device, queued.key, queued.event);
+    }
+
+    self.queue = queue;
+    (change, next_tick)
+  }
+
+  /// Evaluate currently pressed keys based on the provided time and
+  /// report the resulting key state for the current frame.
+  ///
+  /// This method is an alternative to [`tick`][Keys::tick] for
+  /// usage in game loops and similar, where it is more convenient to
+  /// query key state once per frame than to handle individual events.
+  /// It should be invoked once per frame. Memory used for the returned
+  /// [`Frame`] is reused across invocations.
+  ///
+  /// Compared to [`tick`][Keys::tick], auto-repeat cannot be disabled
+  /// for individual keys.
+  pub fn frame(&mut self, now: I) -> &Frame<K>
+  where
+    K: Clone,
+  {
+    let mut frame = take(&mut self.frame);
+    let () = frame.clear();
+
+    let result = self.tick_with(
+      now,
+      |key, fire, _repeat| {
+        let keys = match fire {
+          Fire::Press => &mut frame.pressed,
+          Fire::Repeat => &mut frame.repeated,
+        };
+        let () = push_unique(keys, key);
+        Ok::<_, Infallible>(true)
+      },
+      |key| frame.released.push(key.clone()),
+    );
+    let (_fired, _next_tick) = match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    };
+
+    let () = frame.held.extend(self.iter_pressed().cloned());
+
+    self.frame = frame;
+    &self.frame
+  }
+
+  /// The implementation of [`tick`][Keys::tick], additionally
+  /// reporting the kind of each event and invoking `removed` for all
+  /// keys no longer being tracked.
+  pub(crate) fn tick_with<F, R, C, E>(
+    &mut self,
+    now: I,
+    mut handler: F,
+    mut removed: R,
+  ) -> Result<(C, Option<I>), E>
+  where
+    F: FnMut(&K, Fire, &mut KeyRepeat) -> Result<C, E>,
+    R: FnMut(&K),
+    C: Default + BitOrAssign,
+  {
+    let () = self.observe_time(now);
+    self.last_tick = self.last_tick.max(Some(now));
+
     let mut change = C::default();
     let mut next_tick = None;
+    let config = self.config;
+    let Config {
+      timeout,
+      interval,
+      max_hold,
+      max_catch_up,
+      mode,
+      ..
+    } = config;
+    let platform_repeats = self.platform_repeats;
+    let mut error = None;
+
+    let extracted = self.pressed.extract_if(|key, entry| {
+      if error.is_some() {
+        // Once the handler reported an error we leave all remaining
+        // keys untouched.
+        return false
+      }
+
+      if let Some(deadline) = entry.stuck_deadline(max_hold) {
+        if deadline <= now {
+          // The key has been held down for too long. Release it as of
+          // the deadline, so that only repeats up to that point are
+          // being delivered.
+          let () = entry.release_all(deadline, &config);
+          entry.stuck_at = Some(deadline);
+        }
+      }
 
-    let () = self.pressed.retain(|key, key_state| loop {
-      if let Some(tick) = key_state.next_tick() {
-        if tick > now {
-          next_tick = min_instant(next_tick, Some(tick));
-          break true
+      let keep = loop {
+        if let Some(max_catch_up) = max_catch_up {
+          let () = entry
+            .state
+            .catch_up(now, entry.interval(interval), max_catch_up);
         }
 
-        let mut repeat = KeyRepeat::Enabled;
-        change |= handler(key, &mut repeat);
+        if let Some(tick) = entry.state.next_tick() {
+          if tick > now {
+            next_tick = min_instant(next_tick, Some(tick));
+            break true
+          }
+
+          let fire = entry.next_fire();
+
+          let mut repeat = KeyRepeat::Enabled;
+          match handler(key, fire, &mut repeat) {
+            Ok(result) => change |= result,
+            Err(err) => {
+              // Leave the fire pending, so that it gets delivered on
+              // the next tick.
+              error = Some(err);
+              break true
+            },
+          }
+          let () = entry.on_fire(fire);
+
+          let disable = match repeat {
+            KeyRepeat::Enabled => {
+              let () = entry.state.tick(timeout, entry.interval(interval));
+              false
+            },
+            KeyRepeat::Disabled => true,
+            KeyRepeat::Restart | KeyRepeat::Timeout(..) => {
+              if entry.devices.is_empty() {
+                // There is nothing to restart for a released key.
+                let () = entry.state.tick(timeout, entry.interval(interval));
+              } else {
+                let timeout = match repeat {
+                  KeyRepeat::Timeout(timeout) => timeout,
+                  _ => timeout,
+                };
+                let () = entry.state.restart(now, timeout);
+                entry.control.repeating = false;
+              }
+              false
+            },
+            KeyRepeat::Postpone(by) => {
+              let () = entry.state.tick(timeout, entry.interval(interval));
+              let () = entry.state.postpone(by);
+              false
+            },
+            KeyRepeat::Limit(count) => {
+              entry.control.remaining = Some(count);
+              let () = entry.state.tick(timeout, entry.interval(interval));
+              false
+            },
+            KeyRepeat::Interval(interval) => {
+              entry.control.interval = Some(interval);
+              let () = entry.state.tick(timeout, interval);
+              false
+            },
+          };
+
+          if disable || entry.is_exhausted() || mode == RepeatMode::Disabled {
+            if entry.devices.is_empty() {
+              break false
+            }
+            // Keep track of the key until it is released, so that we
+            // can tell apart its eventual release from bogus ones.
+            let () = entry.state.disable();
+            break true
+          }
 
-        match repeat {
-          KeyRepeat::Disabled => break false,
-          KeyRepeat::Enabled => {
-            let () = key_state.tick(self.timeout, self.interval);
-          },
+          // Once the platform is known to provide repeats, we no
+          // longer synthesize any ourselves.
+          if platform_repeats {
+            let () = entry.state.forward();
+          }
+        } else {
+          // If there is no next tick then the key either had been
+          // released earlier or is held without us repeating it. In
+          // the former case make sure to remove the state after we are
+          // done.
+          break !entry.state.is_released()
         }
-      } else {
-        // If there is no next tick then the key had been released
-        // earlier. Make sure to remove the state after we are done.
-        break false
+      };
+
+      if keep {
+        next_tick = min_instant(next_tick, entry.stuck_deadline(max_hold));
       }
+      !keep
     });
 
-    (change, next_tick)
+    for (key, entry) in extracted {
+      // The release of a suspended key is swallowed.
+      if !entry.control.suspended {
+        let () = removed(&key);
+      }
+      if let Some(released_at) = entry.stuck_at {
+        let () = self.anomalies.record(Anomaly::Stuck {
+          key,
+          pressed_at: entry.state.pressed_at(),
+          released_at,
+        });
+      }
+    }
+
+    if let Some(err) = error {
+      return Err(err)
+    }
+    Ok((change, next_tick))
+  }
+
+  /// Save the state of this object, with all instants expressed
+  /// relative to `epoch`.
+  ///
+  /// `epoch` should not be later than any instant provided so far;
+  /// earlier instants are treated as being equal to it. Anomalies and
+  /// diagnostics are not part of the saved state.
+  pub fn save(&self, epoch: I) -> State<K, D>
+  where
+    K: Clone,
+    D: Clone,
+  {
+    let since = |instant: I| instant - epoch.min(instant);
+
+    State {
+      config: self.config,
+      keys: self
+        .pressed
+        .iter()
+        .map(|(key, entry)| KeyRecord {
+          key: key.clone(),
+          state: entry.state.map(since),
+          devices: entry.devices.clone(),
+          stuck_at: entry.stuck_at.map(since),
+          control: entry.control,
+        })
+        .collect(),
+      last_time: self.last_time.map(since),
+      last_tick: self.last_tick.map(since),
+      platform_repeats: self.platform_repeats,
+      context: self.context,
+    }
+  }
+
+  /// Restore a [`Keys`] object from `state`, with all instants being
+  /// relative to `epoch`.
+  pub fn restore(state: State<K, D>, epoch: I) -> Self {
+    let at = |offset: Duration| epoch + offset;
+
+    Self {
+      config: state.config,
+      pressed: state
+        .keys
+        .into_iter()
+        .map(|record| {
+          let entry = KeyEntry {
+            state: record.state.map(at),
+            devices: record.devices,
+            stuck_at: record.stuck_at.map(at),
+            control: record.control,
+          };
+          (record.key, entry)
+        })
+        .collect(),
+      anomalies: AnomalyLog::new(),
+      last_time: state.last_time.map(at),
+      last_tick: state.last_tick.map(at),
+      platform_repeats: state.platform_repeats,
+      context: state.context,
+      frame: Frame::default(),
+      queue: Queue::default(),
+    }
+  }
+
+  /// Take a [`Snapshot`] of the current state of this object.
+  ///
+  /// Rewinding to the snapshot via [`rewind`][Keys::rewind] and
+  /// feeding the same sequence of events results in the exact same
+  /// sequence of key presses and repeats being reported as when
+  /// feeding said events without rewinding. That makes snapshots
+  /// suitable for rollback based netcode, for example.
+  pub fn snapshot(&self) -> Snapshot<K, I, D>
+  where
+    K: Clone,
+    D: Clone,
+  {
+    Snapshot {
+      pressed: self.pressed.clone(),
+      last_time: self.last_time,
+      last_tick: self.last_tick,
+      platform_repeats: self.platform_repeats,
+      context: self.context,
+    }
+  }
+
+  /// Rewind the state of this object to that captured in `snapshot`.
+  ///
+  /// The configuration as well as anomalies are unaffected.
+  pub fn rewind(&mut self, snapshot: &Snapshot<K, I, D>)
+  where
+    K: Clone,
+    D: Clone,
+  {
+    let () = self.pressed.clone_from(&snapshot.pressed);
+    self.last_time = snapshot.last_time;
+    self.last_tick = snapshot.last_tick;
+    self.platform_repeats = snapshot.platform_repeats;
+    self.context = snapshot.context;
+  }
+
+  /// Retrieve and remove all anomalies encountered so far.
+  ///
+  /// Only the most recent anomalies are retained until retrieved; older
+  /// ones get dropped. Counters as per
+  /// [`diagnostics`][Keys::diagnostics] are unaffected by that.
+  #[inline]
+  pub fn drain_anomalies(&mut self) -> Drain<'_, Anomaly<K, I>> {
+    self.anomalies.drain()
+  }
+
+  /// Enable diagnostics, i.e., the reporting of anomalies caused by
+  /// input events as well as the counting of all anomalies.
+  ///
+  /// Diagnostics are disabled by default.
+  #[inline]
+  pub fn enable_diagnostics(&mut self)
+  where
+    K: Clone,
+  {
+    self.anomalies.enable_diagnostics()
+  }
+
+  /// Retrieve counters of all anomalies encountered since diagnostics
+  /// got enabled, if they are.
+  #[inline]
+  pub fn diagnostics(&self) -> Option<&Diagnostics> {
+    self.anomalies.diagnostics()
   }
 
   /// Clear all pressed keys, i.e., marking them all as released.
+  ///
+  /// No pending presses or repeats are delivered for any of the keys.
+  /// To only drop the state of keys held by a single device, use
+  /// [`forget_device`][Keys::forget_device] instead.
   #[inline]
   pub fn clear(&mut self) {
     self.pressed.clear()
@@ -346,6 +1677,9 @@ mod tests {
   use std::cell::Cell;
   use std::ops::BitOr;
 
+  use crate::anomaly::MAX_ANOMALIES;
+  use crate::config::DEFAULT_MAX_CATCH_UP;
+
   type Key = char;
 
   /// A `Duration` of one second.
@@ -583,4 +1917,1101 @@ mod tests {
     assert_eq!(change, Change::Unchanged);
     assert_eq!(tick, None);
   }
+
+
+  /// Check that a key pressed on multiple devices is only considered
+  /// released once all of them released it.
+  #[test]
+  fn multi_device_press_release() {
+    let a_pressed = Cell::new(0);
+
+    let mut handler = |key: &Key, _repeat: &mut KeyRepeat| match key {
+      'a' => {
+        a_pressed.set(a_pressed.get() + 1);
+        Change::Changed
+      },
+      _ => Change::Unchanged,
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key, Instant, u8>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_device_key_press(now, 1, 'a');
+    let () = keys.on_device_key_press(now + 1 * SECOND, 2, 'a');
+    let (change, tick) = keys.tick(now + 1 * SECOND, &mut handler);
+    assert_eq!(a_pressed.get(), 1);
+    assert_eq!(change, Change::Changed);
+    assert_eq!(tick, Some(now + 5 * SECOND));
+
+    // Device 2 still holds the key, so it should keep repeating.
+    let () = keys.on_device_key_release(now + 2 * SECOND, 1, 'a');
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(a_pressed.get(), 3);
+    assert_eq!(tick, Some(now + 7 * SECOND));
+
+    // A release from a device not holding the key is ignored.
+    let () = keys.on_device_key_release(now + 6 * SECOND, 3, 'a');
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(tick, Some(now + 7 * SECOND));
+
+    let () = keys.on_device_key_release(now + 6 * SECOND, 2, 'a');
+    let (change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(a_pressed.get(), 3);
+    assert_eq!(change, Change::Unchanged);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that we can release all keys held by a single device.
+  #[test]
+  fn device_release() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key, Instant, u8>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_device_key_press(now, 1, 'a');
+    let () = keys.on_device_key_press(now, 1, 'b');
+    let () = keys.on_device_key_press(now, 2, 'b');
+    let () = keys.release_device(now + 1 * SECOND, &1);
+
+    let (_change, tick) = keys.tick(now + 1 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    // Only 'b' is still held (by device 2).
+    assert_eq!(tick, Some(now + 5 * SECOND));
+
+    let (_change, tick) = keys.tick(now + 5 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 3);
+    assert_eq!(tick, Some(now + 6 * SECOND));
+  }
+
+
+  /// Check that we can silently drop the state of all keys held by a
+  /// single device.
+  #[test]
+  fn device_forget() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key, Instant, u8>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_device_key_press(now, 1, 'a');
+    let () = keys.on_device_key_press(now, 1, 'b');
+    let () = keys.on_device_key_press(now, 2, 'b');
+    // 'c' got released already, but it still has a press pending.
+    let () = keys.on_device_key_press(now, 1, 'c');
+    let () = keys.on_device_key_release(now, 1, 'c');
+    let () = keys.forget_device(&1);
+
+    let (_change, tick) = keys.tick(now + 1 * SECOND, &mut handler);
+    // Only 'b' and 'c' should have been reported.
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, Some(now + 5 * SECOND));
+
+    // A release of a forgotten key is ignored.
+    let () = keys.on_device_key_release(now + 2 * SECOND, 1, 'a');
+    let () = keys.on_device_key_release(now + 2 * SECOND, 2, 'b');
+    let (_change, tick) = keys.tick(now + 2 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that releasing all keys still delivers pending presses and
+  /// repeats.
+  #[test]
+  fn all_keys_release() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key, Instant, u8>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_device_key_press(now, 1, 'a');
+    let () = keys.on_device_key_press(now, 2, 'a');
+    let () = keys.on_device_key_press(now + 1 * SECOND, 1, 'b');
+    let () = keys.release_all(now + 6 * SECOND);
+
+    // 'a' was pressed and repeated twice, 'b' just pressed.
+    let (_change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 4);
+    assert_eq!(tick, None);
+
+    // Releases received later on are just ignored.
+    let () = keys.on_device_key_release(now + 11 * SECOND, 2, 'a');
+    let (_change, tick) = keys.tick(now + 11 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 4);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Make sure that keys held down for longer than the configured
+  /// maximum are force-released and reported as stuck.
+  #[test]
+  fn stuck_key_release() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.max_hold = Some(10 * SECOND);
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'x');
+    let () = keys.on_key_press(now + 8 * SECOND, 'y');
+    let (_change, tick) = keys.tick(now + 9 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 7);
+    assert_eq!(tick, Some(now + 10 * SECOND));
+    assert_eq!(keys.drain_anomalies().next(), None);
+
+    // No repeats past the deadline should be delivered, even if we tick
+    // late.
+    let (_change, tick) = keys.tick(now + 12 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 7);
+    assert_eq!(tick, Some(now + 13 * SECOND));
+
+    let anomalies = keys.drain_anomalies().collect::<Vec<_>>();
+    assert_eq!(
+      anomalies,
+      vec![Anomaly::Stuck {
+        key: 'x',
+        pressed_at: now,
+        released_at: now + 10 * SECOND,
+      }]
+    );
+    assert_eq!(keys.drain_anomalies().next(), None);
+  }
+
+
+  /// Check that large jumps in time are treated as a discontinuity, if
+  /// so configured.
+  #[test]
+  fn time_jump() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.max_gap = Some(60 * SECOND);
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'j');
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 3);
+    assert_eq!(tick, Some(now + 7 * SECOND));
+
+    // A gap within the limits is just regular passage of time.
+    let (_change, tick) = keys.tick(now + 66 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 63);
+    assert_eq!(tick, Some(now + 67 * SECOND));
+    assert_eq!(keys.drain_anomalies().next(), None);
+
+    // An hour long "suspend" should not result in any catch-up repeats.
+    let later = now + 3666 * SECOND;
+    let (_change, tick) = keys.tick(later, &mut handler);
+    assert_eq!(pressed.get(), 63);
+    assert_eq!(tick, Some(later + 1 * SECOND));
+    // The time of the press is unaffected.
+    assert_eq!(keys.held_for(&'j', later), Some(3666 * SECOND));
+    assert_eq!(
+      keys.drain_anomalies().collect::<Vec<_>>(),
+      vec![Anomaly::TimeJump {
+        from: now + 66 * SECOND,
+        to: later,
+      }]
+    );
+
+    // The same applies to a jump observed on release.
+    let () = keys.on_key_release(later + 7200 * SECOND, 'j');
+    let (_change, tick) = keys.tick(later + 7200 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 63);
+    assert_eq!(tick, None);
+    assert_eq!(keys.drain_anomalies().count(), 1);
+  }
+
+
+  /// Check that a key held across a time jump is still considered
+  /// stuck once it has been held for the maximum duration.
+  #[test]
+  fn time_jump_stuck() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.max_gap = Some(60 * SECOND);
+    config.max_hold = Some(600 * SECOND);
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'j');
+    let (_change, _tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 3);
+
+    // The release got lost during an hour long "suspend". The key is
+    // released as of its deadline, without any catch-up repeats.
+    let (_change, tick) = keys.tick(now + 3666 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 3);
+    assert_eq!(tick, None);
+    assert!(!keys.is_pressed(&'j'));
+    let stuck = keys
+      .drain_anomalies()
+      .filter(|anomaly| matches!(anomaly, Anomaly::Stuck { .. }))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      stuck,
+      vec![Anomaly::Stuck {
+        key: 'j',
+        pressed_at: now,
+        released_at: now + 600 * SECOND,
+      }]
+    );
+  }
+
+
+  /// Check that the number of repeats accumulated until release is
+  /// capped, even for tiny intervals.
+  #[test]
+  fn release_tiny_interval() {
+    let now = Instant::now();
+    let mut state = KeyState::pressed(now);
+    let () = state.on_release(
+      now + 3600 * SECOND,
+      TIMEOUT,
+      Duration::from_nanos(1),
+      Some(100),
+    );
+    assert!(matches!(
+      state,
+      KeyState::ReleasePending {
+        fire_count: 101,
+        ..
+      }
+    ));
+  }
+
+
+  /// Check that the number of repeats caught up on is limited by
+  /// default.
+  #[test]
+  fn catch_up_default() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(SECOND, Duration::from_nanos(1));
+
+    let () = keys.on_key_press(now, 'j');
+    let (_change, _tick) = keys.tick(now + 3600 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 1 + DEFAULT_MAX_CATCH_UP);
+
+    let () = keys.on_key_release(now + 7200 * SECOND, 'j');
+    let (_change, tick) = keys.tick(now + 7200 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 1 + 2 * DEFAULT_MAX_CATCH_UP);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that late "ticks" only catch up on a limited number of
+  /// repeats.
+  #[test]
+  fn catch_up_limit() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, Duration::from_nanos(1));
+    config.max_catch_up = Some(10);
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'j');
+    assert_eq!(keys.pending_fires(&'j', now + 6 * SECOND), 11);
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 11);
+    assert_eq!(tick, Some(now + 6 * SECOND + Duration::from_nanos(1)));
+
+    let () = keys.on_key_release(now + 7 * SECOND, 'j');
+    let (_change, tick) = keys.tick(now + 7 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 21);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that releases of keys that are not pressed are ignored and
+  /// reported.
+  #[test]
+  fn bogus_release() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |key: &Key, repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      if *key == 'd' {
+        *repeat = KeyRepeat::Disabled;
+      }
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let () = keys.enable_diagnostics();
+
+    // A release before any press.
+    let () = keys.on_key_release(now, 'a');
+    let () = keys.on_key_press(now, 'a');
+    let () = keys.on_key_press(now, 'd');
+    let () = keys.on_key_release(now + 1 * SECOND, 'a');
+    // A duplicate release.
+    let () = keys.on_key_release(now + 1 * SECOND, 'a');
+
+    let (_change, tick) = keys.tick(now + 1 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, None);
+
+    // The release of a key with auto-repeat disabled is expected.
+    let () = keys.on_key_release(now + 2 * SECOND, 'd');
+    let (_change, tick) = keys.tick(now + 2 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, None);
+
+    assert_eq!(
+      keys.drain_anomalies().collect::<Vec<_>>(),
+      vec![
+        Anomaly::UnknownRelease { key: 'a', at: now },
+        Anomaly::DuplicateRelease {
+          key: 'a',
+          at: now + 1 * SECOND,
+        },
+      ]
+    );
+  }
+
+
+  /// Check that we handle events with timestamps "from the past"
+  /// gracefully.
+  #[test]
+  fn out_of_order_events() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let () = keys.enable_diagnostics();
+
+    let (_change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(tick, None);
+
+    // A press predating the last tick is treated as if it happened at
+    // the time of said tick.
+    let () = keys.on_key_press(now + 5 * SECOND, 'o');
+    let (_change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 1);
+    assert_eq!(tick, Some(now + 15 * SECOND));
+
+    // Same for a release.
+    let () = keys.on_key_release(now + 9 * SECOND, 'o');
+    let (_change, tick) = keys.tick(now + 11 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 1);
+    assert_eq!(tick, None);
+
+    // A release timestamped before its press is treated as happening
+    // at the time of the press.
+    let () = keys.on_key_press(now + 20 * SECOND, 'p');
+    let () = keys.on_key_release(now + 15 * SECOND, 'p');
+    let (_change, tick) = keys.tick(now + 30 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, None);
+
+    assert_eq!(
+      keys.drain_anomalies().collect::<Vec<_>>(),
+      vec![
+        Anomaly::OutOfOrder {
+          key: 'o',
+          at: now + 5 * SECOND,
+          adjusted: now + 10 * SECOND,
+        },
+        Anomaly::OutOfOrder {
+          key: 'o',
+          at: now + 9 * SECOND,
+          adjusted: now + 10 * SECOND,
+        },
+        Anomaly::OutOfOrder {
+          key: 'p',
+          at: now + 15 * SECOND,
+          adjusted: now + 20 * SECOND,
+        },
+      ]
+    );
+  }
+
+
+  /// Check that anomalies caused by input events are only reported
+  /// once diagnostics are enabled.
+  #[test]
+  fn diagnostics() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_key_press(now, 'a');
+    let () = keys.on_key_press(now, 'a');
+    let () = keys.on_key_release(now, 'b');
+    assert_eq!(keys.drain_anomalies().next(), None);
+    assert_eq!(keys.diagnostics(), None);
+
+    let () = keys.enable_diagnostics();
+    let () = keys.on_key_press(now + 1 * SECOND, 'a');
+    let () = keys.on_key_press(now + 2 * SECOND, 'a');
+    let () = keys.on_key_release(now + 3 * SECOND, 'b');
+    assert_eq!(
+      keys.drain_anomalies().collect::<Vec<_>>(),
+      vec![
+        Anomaly::DuplicatePress {
+          key: 'a',
+          at: now + 1 * SECOND,
+        },
+        Anomaly::DuplicatePress {
+          key: 'a',
+          at: now + 2 * SECOND,
+        },
+        Anomaly::UnknownRelease {
+          key: 'b',
+          at: now + 3 * SECOND,
+        },
+      ]
+    );
+
+    let diagnostics = keys.diagnostics().unwrap();
+    assert_eq!(diagnostics.duplicate_presses, 2);
+    assert_eq!(diagnostics.unknown_releases, 1);
+    assert_eq!(diagnostics.duplicate_releases, 0);
+  }
+
+
+  /// Check that the anomaly log does not grow without bounds if never
+  /// drained.
+  #[test]
+  fn diagnostics_bounded() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let () = keys.enable_diagnostics();
+
+    let () = keys.on_key_press(now, 'a');
+    for i in 1..=1000 {
+      let () = keys.on_key_press(now + i * INTERVAL, 'a');
+    }
+    assert_eq!(keys.diagnostics().unwrap().duplicate_presses, 1000);
+
+    let anomalies = keys.drain_anomalies().collect::<Vec<_>>();
+    assert_eq!(anomalies.len(), MAX_ANOMALIES);
+    assert_eq!(
+      anomalies.last(),
+      Some(&Anomaly::DuplicatePress {
+        key: 'a',
+        at: now + 1000 * INTERVAL,
+      })
+    );
+  }
+
+
+  /// Check that no repeats are generated if auto-repeat is disabled.
+  #[test]
+  fn disabled_repeats() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.mode = RepeatMode::Disabled;
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'a');
+    assert_eq!(keys.pending_fires(&'a', now + 10 * SECOND), 1);
+    let (_change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 1);
+    assert_eq!(tick, None);
+    assert!(keys.is_pressed(&'a'));
+
+    // A key released before the first "tick" is reported once as well.
+    let () = keys.on_key_press(now + 11 * SECOND, 'b');
+    let () = keys.on_key_release(now + 20 * SECOND, 'b');
+    let (_change, tick) = keys.tick(now + 20 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, None);
+    assert_eq!(keys.phase(&'b'), None);
+  }
+
+
+  /// Check that platform generated repeats are forwarded in hybrid
+  /// mode, once observed.
+  #[test]
+  fn hybrid_repeats() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.mode = RepeatMode::Hybrid;
+    let mut keys = Keys::<Key>::with_config(config);
+
+    // Without any platform repeats, we synthesize them.
+    let () = keys.on_key_press(now, 'a');
+    let (_change, tick) = keys.tick(now + 5 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 2);
+    assert_eq!(tick, Some(now + 6 * SECOND));
+
+    // Once the platform provides repeats, we just forward them.
+    let () = keys.on_key_repeat(now + 5 * SECOND + SECOND / 2, 'a');
+    let () = keys.on_key_repeat(now + 6 * SECOND, 'a');
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 4);
+    assert_eq!(tick, None);
+
+    let (_change, tick) = keys.tick(now + 10 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 4);
+    assert_eq!(tick, None);
+
+    let () = keys.on_key_repeat(now + 10 * SECOND, 'a');
+    let () = keys.on_key_release(now + 10 * SECOND, 'a');
+    let (_change, tick) = keys.tick(now + 11 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 5);
+    assert_eq!(tick, None);
+
+    // Subsequently pressed keys are no longer repeated by us.
+    let () = keys.on_key_press(now + 12 * SECOND, 'b');
+    let (_change, tick) = keys.tick(now + 20 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 6);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that platform generated repeats are ignored by default.
+  #[test]
+  fn synthesized_repeats() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let () = keys.enable_diagnostics();
+
+    // A repeat of an unknown key is treated as press.
+    let () = keys.on_key_repeat(now, 'a');
+    let () = keys.on_key_repeat(now + 1 * SECOND, 'a');
+    let (_change, tick) = keys.tick(now + 6 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 3);
+    assert_eq!(tick, Some(now + 7 * SECOND));
+    assert_eq!(keys.drain_anomalies().next(), None);
+  }
+
+
+  /// Check that we can save and restore the state of a `Keys` object.
+  #[test]
+  fn save_restore() {
+    let pressed = Cell::new(0);
+
+    let mut handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    };
+
+    let now = Instant::now();
+    let mut keys = Keys::<Key, Instant, u8>::new(TIMEOUT, INTERVAL);
+    let () = keys.on_device_key_press(now + 1 * SECOND, 1, 'a');
+    let () = keys.on_device_key_press(now + 2 * SECOND, 2, 'b');
+    let (_change, tick) = keys.tick(now + 7 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 5);
+    assert_eq!(tick, Some(now + 8 * SECOND));
+    let () = keys.on_device_key_release(now + 7 * SECOND + SECOND / 2, 2, 'b');
+
+    let state = keys.save(now);
+    assert_eq!(state.config(), keys.config());
+
+    // Restore relative to a different epoch.
+    let epoch = now + 100 * SECOND;
+    let mut keys = Keys::restore(state, epoch);
+    let (_change, tick) = keys.tick(epoch + 8 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 6);
+    assert_eq!(tick, Some(epoch + 9 * SECOND));
+
+    let () = keys.on_device_key_release(epoch + 9 * SECOND, 1, 'a');
+    let (_change, tick) = keys.tick(epoch + 9 * SECOND, &mut handler);
+    assert_eq!(pressed.get(), 6);
+    assert_eq!(tick, None);
+  }
+
+
+  /// Check that we can serialize and deserialize the state of a `Keys`
+  /// object.
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serialize_deserialize_state() {
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.max_hold = Some(60 * SECOND);
+    let mut keys = Keys::<Key>::with_config(config);
+    let () = keys.on_key_press(now + 1 * SECOND, 'a');
+    let (_change, tick) = keys.tick(now + 1 * SECOND, |_key, _repeat| Change::Changed);
+    assert_eq!(tick, Some(now + 6 * SECOND));
+
+    let json = serde_json::to_string(&keys.save(now)).unwrap();
+    let state = serde_json::from_str::<State<Key>>(&json).unwrap();
+    assert_eq!(state.config(), &config);
+
+    let mut keys = Keys::restore(state, now);
+    let (change, tick) = keys.tick(now + 2 * SECOND, |_key, _repeat| Change::Changed);
+    assert_eq!(change, Change::Unchanged);
+    assert_eq!(tick, Some(now + 6 * SECOND));
+  }
+
+
+  /// Check that rewinding to a snapshot and re-feeding events yields
+  /// the same sequence of reported keys as a run without rewinding.
+  #[test]
+  fn snapshot_rewind() {
+    /// Run a single "frame", returning the keys reported.
+    fn frame(keys: &mut Keys<Key>, now: Instant, events: &[(bool, Key)]) -> Vec<Key> {
+      let () = events.iter().for_each(|(press, key)| {
+        if *press {
+          keys.on_key_press(now, *key)
+        } else {
+          keys.on_key_release(now, *key)
+        }
+      });
+
+      let mut reported = Vec::new();
+      let _result = keys.tick(now, |key, _repeat| {
+        let () = reported.push(*key);
+        Change::Changed
+      });
+      reported
+    }
+
+    let now = Instant::now();
+    let keys = "abcdefghijklmnopqrstuvwxyz".chars().collect::<Vec<_>>();
+    let events = |frame: u32, correct: bool| -> Vec<(bool, Key)> {
+      match frame {
+        0 => keys.iter().map(|key| (true, *key)).collect(),
+        3 if correct => vec![(false, 'c'), (true, '1'), (true, '2')],
+        3 => vec![(false, 'x')],
+        6 => vec![(false, 'a'), (true, 'c')],
+        _ => Vec::new(),
+      }
+    };
+
+    // The reference run, using the correct inputs throughout.
+    let mut reference = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let expected = (0..12)
+      .map(|i| frame(&mut reference, now + i * SECOND, &events(i, true)))
+      .collect::<Vec<_>>();
+
+    // A run mispredicting the inputs of frame 3.
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let mut reported = Vec::new();
+    let mut snapshot = None;
+    for i in 0..8 {
+      if i == 3 {
+        snapshot = Some(keys.snapshot());
+      }
+      let () = reported.push(frame(&mut keys, now + i * SECOND, &events(i, false)));
+    }
+
+    // Roll back and re-simulate with the corrected inputs.
+    let () = keys.rewind(snapshot.as_ref().unwrap());
+    let () = reported.truncate(3);
+    for i in 3..12 {
+      let () = reported.push(frame(&mut keys, now + i * SECOND, &events(i, true)));
+    }
+
+    assert_eq!(reported, expected);
+  }
+
+
+  /// Check that [`Keys::frame`] reports the expected key sets.
+  #[test]
+  fn frame_sets() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+
+    let () = keys.on_key_press(now, 'a');
+    let frame = keys.frame(now);
+    assert_eq!(frame.pressed(), &['a']);
+    assert_eq!(frame.held(), &['a']);
+    assert_eq!(frame.repeated(), &[] as &[Key]);
+    assert_eq!(frame.released(), &[] as &[Key]);
+
+    let frame = keys.frame(now + 2 * SECOND);
+    assert_eq!(frame.pressed(), &[] as &[Key]);
+    assert_eq!(frame.held(), &['a']);
+
+    // Multiple repeats within a single frame are reported once.
+    let frame = keys.frame(now + 7 * SECOND);
+    assert_eq!(frame.repeated(), &['a']);
+
+    // A key pressed and released in between frames is still reported
+    // as pressed.
+    let () = keys.on_key_press(now + 7 * SECOND + SECOND / 2, 'b');
+    let () = keys.on_key_release(now + 7 * SECOND + SECOND / 2, 'b');
+    let frame = keys.frame(now + 8 * SECOND);
+    assert!(frame.is_pressed(&'b'));
+    assert!(frame.is_released(&'b'));
+    assert!(!frame.is_held(&'b'));
+    assert!(frame.is_held(&'a'));
+    assert!(frame.is_repeated(&'a'));
+
+    let () = keys.on_key_release(now + 8 * SECOND + SECOND / 2, 'a');
+    let frame = keys.frame(now + 9 * SECOND);
+    assert_eq!(frame.released(), &['a']);
+    assert_eq!(frame.held(), &[] as &[Key]);
+    assert_eq!(frame.repeated(), &[] as &[Key]);
+  }
+
+
+  /// Check that [`Keys::frame`] reports platform generated repeats
+  /// received before the first frame as repeats.
+  #[test]
+  fn frame_hybrid() {
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.mode = RepeatMode::Hybrid;
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'a');
+    let () = keys.on_key_repeat(now + SECOND / 2, 'a');
+    let frame = keys.frame(now + SECOND / 2);
+    assert_eq!(frame.pressed(), &['a']);
+    assert_eq!(frame.repeated(), &['a']);
+
+    let () = keys.on_key_repeat(now + SECOND, 'a');
+    let frame = keys.frame(now + SECOND);
+    assert_eq!(frame.pressed(), &[] as &[Key]);
+    assert_eq!(frame.repeated(), &['a']);
+  }
+
+
+  /// Check that we can query the state of keys using borrowed forms.
+  #[test]
+  fn query_state() {
+    let now = Instant::now();
+    let mut keys = Keys::<String>::new(TIMEOUT, INTERVAL);
+
+    assert!(!keys.is_pressed("shift"));
+    assert_eq!(keys.phase("shift"), None);
+
+    let () = keys.on_key_press(now, "shift".to_string());
+    let () = keys.on_key_press(now + SECOND, "a".to_string());
+    assert!(keys.is_pressed("shift"));
+    assert_eq!(keys.pressed_at("shift"), Some(now));
+    assert_eq!(keys.held_for("shift", now + 2 * SECOND), Some(2 * SECOND));
+    assert_eq!(keys.phase("shift"), Some(Phase::Pressed));
+    assert_eq!(keys.pending_fires("shift", now), 1);
+
+    let mut pressed = keys.iter_pressed().cloned().collect::<Vec<_>>();
+    let () = pressed.sort();
+    assert_eq!(pressed, vec!["a".to_string(), "shift".to_string()]);
+
+    let _result = keys.tick(now, |_key, _repeat| Change::Changed);
+    assert_eq!(keys.phase("shift"), Some(Phase::Pressed));
+    assert_eq!(keys.pending_fires("shift", now + 4 * SECOND), 0);
+    assert_eq!(keys.pending_fires("shift", now + 7 * SECOND), 3);
+
+    let _result = keys.tick(now + 7 * SECOND, |_key, _repeat| Change::Changed);
+    assert_eq!(keys.phase("shift"), Some(Phase::Repeating));
+
+    let () = keys.on_key_release(now + 7 * SECOND + SECOND / 2, "shift".to_string());
+    assert!(!keys.is_pressed("shift"));
+    assert_eq!(keys.held_for("shift", now + 8 * SECOND), None);
+    assert_eq!(keys.phase("shift"), Some(Phase::Released));
+
+    let _result = keys.tick(now + 8 * SECOND, |_key, _repeat| Change::Changed);
+    assert_eq!(keys.phase("shift"), None);
+    assert_eq!(keys.iter_pressed().collect::<Vec<_>>(), vec!["a"]);
+  }
+
+
+  /// Check that counting pending fires is cheap even if a huge number
+  /// of repeats is due.
+  #[test]
+  fn pending_fires_many() {
+    let now = Instant::now();
+    let mut config = Config::new(SECOND, Duration::from_nanos(1));
+    config.max_catch_up = None;
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'a');
+    // The press, the first repeat after one second, and one repeat for
+    // every nanosecond thereafter.
+    assert_eq!(
+      keys.pending_fires(&'a', now + 3600 * SECOND),
+      2 + 3_599_000_000_000
+    );
+
+    let pressed = Cell::new(0);
+    let mut keys = Keys::<Key>::new(SECOND, Duration::from_nanos(1));
+    let () = keys.on_key_press(now, 'a');
+    let fires = keys.pending_fires(&'a', now + 3600 * SECOND);
+    let _result = keys.tick(now + 3600 * SECOND, |_key, _repeat| {
+      pressed.set(pressed.get() + 1);
+      Change::Changed
+    });
+    assert_eq!(fires, pressed.get());
+  }
+
+
+  /// Check that a failing handler passed to [`Keys::try_tick`] leaves
+  /// the failing and all remaining fires pending.
+  #[test]
+  fn try_tick_resume() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let mut delivered = Vec::new();
+    let calls = Cell::new(0);
+    // A handler failing on its third invocation.
+    let mut handler = |key: &Key, _repeat: &mut KeyRepeat| {
+      let () = calls.set(calls.get() + 1);
+      if calls.get() == 3 {
+        Err("channel full")
+      } else {
+        let () = delivered.push(*key);
+        Ok(Change::Changed)
+      }
+    };
+
+    let () = keys.on_key_press(now, 'a');
+    let () = keys.on_key_press(now, 'b');
+    let result = keys.try_tick(now + 7 * SECOND, &mut handler);
+    assert_eq!(result, Err("channel full"));
+
+    let (change, next) = keys.try_tick(now + 7 * SECOND, &mut handler).unwrap();
+    assert_eq!(change, Change::Changed);
+    assert_eq!(next, Some(now + 8 * SECOND));
+
+    // Each key should have been pressed and repeated three times, with
+    // nothing lost or duplicated.
+    let () = delivered.sort();
+    assert_eq!(delivered, "aaaabbbb".chars().collect::<Vec<_>>());
+    assert_eq!(calls.get(), 9);
+  }
+
+
+  /// Check that a key force-released during a failing
+  /// [`Keys::try_tick`] is still reported as stuck after a save and
+  /// restore.
+  #[test]
+  fn try_tick_stuck_restore() {
+    let now = Instant::now();
+    let mut config = Config::new(TIMEOUT, INTERVAL);
+    config.max_hold = Some(10 * SECOND);
+    let mut keys = Keys::<Key>::with_config(config);
+
+    let () = keys.on_key_press(now, 'a');
+    let result = keys.try_tick(now + 20 * SECOND, |_key, _repeat| {
+      Err::<Change, _>("channel full")
+    });
+    assert_eq!(result, Err("channel full"));
+
+    let state = keys.save(now);
+    let mut keys = Keys::<Key>::restore(state, now);
+    let _result = keys.tick(now + 20 * SECOND, |_key, _repeat| Change::Changed);
+    assert_eq!(
+      keys.drain_anomalies().collect::<Vec<_>>(),
+      vec![Anomaly::Stuck {
+        key: 'a',
+        pressed_at: now,
+        released_at: now + 10 * SECOND,
+      }]
+    );
+  }
+
+
+  /// Check that the handler can limit the number of repeats.
+  #[test]
+  fn repeat_limit() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let fired = Cell::new(0);
+    let handler = |repeat: Option<KeyRepeat>| {
+      let fired = &fired;
+      move |_key: &Key, r: &mut KeyRepeat| {
+        let () = fired.set(fired.get() + 1);
+        if let Some(repeat) = repeat {
+          *r = repeat;
+        }
+        Change::Changed
+      }
+    };
+
+    let () = keys.on_key_press(now, 'a');
+    let (_, next) = keys.tick(now, handler(Some(KeyRepeat::Limit(2))));
+    assert_eq!(next, Some(now + 5 * SECOND));
+
+    let (_, next) = keys.tick(now + 20 * SECOND, handler(None));
+    assert_eq!(next, None);
+    assert_eq!(fired.get(), 3);
+    assert_eq!(keys.phase(&'a'), Some(Phase::Disabled));
+    assert_eq!(keys.pending_fires(&'a', now + 30 * SECOND), 0);
+
+    // A new press should not be subject to the earlier limit.
+    let () = keys.on_key_release(now + 21 * SECOND, 'a');
+    let () = keys.on_key_press(now + 22 * SECOND, 'a');
+    assert_eq!(keys.pending_fires(&'a', now + 29 * SECOND), 4);
+  }
+
+
+  /// Check that the handler can restart, postpone, and change the
+  /// interval of repeats.
+  #[test]
+  fn repeat_timing() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let handler = |repeat: KeyRepeat| {
+      move |_key: &Key, r: &mut KeyRepeat| {
+        *r = repeat;
+        Change::Changed
+      }
+    };
+
+    let () = keys.on_key_press(now, 'a');
+    let (_, next) = keys.tick(now, handler(KeyRepeat::Enabled));
+    assert_eq!(next, Some(now + 5 * SECOND));
+
+    let (_, next) = keys.tick(now + 5 * SECOND, handler(KeyRepeat::Restart));
+    assert_eq!(next, Some(now + 10 * SECOND));
+    assert_eq!(keys.phase(&'a'), Some(Phase::Pressed));
+
+    let (_, next) = keys.tick(now + 10 * SECOND, handler(KeyRepeat::Postpone(3 * SECOND)));
+    assert_eq!(next, Some(now + 14 * SECOND));
+    assert_eq!(keys.phase(&'a'), Some(Phase::Repeating));
+
+    let (_, next) = keys.tick(now + 14 * SECOND, handler(KeyRepeat::Interval(2 * SECOND)));
+    assert_eq!(next, Some(now + 16 * SECOND));
+    assert_eq!(keys.pending_fires(&'a', now + 20 * SECOND), 3);
+
+    // The release should take the key's interval into account.
+    let () = keys.on_key_release(now + 20 * SECOND, 'a');
+    assert_eq!(keys.pending_fires(&'a', now + 20 * SECOND), 3);
+
+    let () = keys.on_key_press(now + 30 * SECOND, 'b');
+    let (_, next) = keys.tick(now + 30 * SECOND, handler(KeyRepeat::Timeout(SECOND)));
+    assert_eq!(next, Some(now + 31 * SECOND));
+  }
+
+
+  /// Check that events queued by the handler are applied after the
+  /// "tick", in order.
+  #[test]
+  fn tick_queued() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let mut reported = Vec::new();
+    let mut handler = |key: &Key, _repeat: &mut KeyRepeat, queue: &mut Queue<Key, Instant>| {
+      if *key == 'm' {
+        let () = queue.press(now, 'x');
+        let () = queue.release(now, 'x');
+        let () = queue.press(now + SECOND, 'y');
+      }
+      let () = reported.push(*key);
+      Change::Changed
+    };
+
+    let () = keys.on_key_press(now, 'm');
+    let (_, next) = keys.tick_queued(now, &mut handler);
+    assert_eq!(next, Some(now));
+    assert!(!keys.is_pressed(&'x'));
+    assert!(keys.is_pressed(&'y'));
+
+    let (_, next) = keys.tick_queued(now, &mut handler);
+    assert_eq!(next, Some(now + SECOND));
+
+    let (_, next) = keys.tick_queued(now + SECOND, &mut handler);
+    assert_eq!(next, Some(now + 5 * SECOND));
+    assert_eq!(reported, vec!['m', 'x', 'y']);
+  }
+
+
+  /// Check that pushing an input context suspends keys held down.
+  #[test]
+  fn context_suspend() {
+    let now = Instant::now();
+    let mut keys = Keys::<Key>::new(TIMEOUT, INTERVAL);
+    let fired = Cell::new(0);
+    let handler = |_key: &Key, _repeat: &mut KeyRepeat| {
+      let () = fired.set(fired.get() + 1);
+      Change::Changed
+    };
+
+    let () = keys.on_key_press(now, 'w');
+    let _result = keys.tick(now, handler);
+    assert_eq!(fired.get(), 1);
+
+    let () = keys.push_context();
+    assert_eq!(keys.context(), 1);
+    let (_, next) = keys.tick(now + 7 * SECOND, handler);
+    assert_eq!(next, None);
+    assert_eq!(fired.get(), 1);
+    assert!(!keys.is_pressed(&'w'));
+    assert_eq!(keys.phase(&'w'), Some(Phase::Suspended));
+    assert!(keys.frame(now + 8 * SECOND).held().is_empty());
+
+    // The release should be swallowed.
+    let () = keys.on_key_release(now + 9 * SECOND, 'w');
+    assert!(keys.frame(now + 10 * SECOND).released().is_empty());
+    assert_eq!(keys.phase(&'w'), None);
+
+    // Once pressed again the key registers as usual.
+    let () = keys.on_key_press(now + 11 * SECOND, 'w');
+    assert_eq!(keys.frame(now + 11 * SECOND).pressed(), &['w']);
+
+    assert!(keys.pop_context());
+    assert_eq!(keys.phase(&'w'), Some(Phase::Suspended));
+    assert!(!keys.pop_context());
+    assert_eq!(keys.context(), 0);
+  }
 }
@@ -0,0 +1,345 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for resolving physical keys to logical ones via
+//! layers.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::BitOrAssign;
+use std::time::Instant;
+
+use crate::Config;
+use crate::KeyRepeat;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// A layer activated by holding down a layer key.
+#[derive(Clone, Debug)]
+struct Layer<P, L> {
+  /// The physical key activating the layer.
+  activator: P,
+  /// The mapping from physical to logical keys.
+  keys: HashMap<P, L>,
+}
+
+
+/// A layer mechanism on top of [`Keys`], resolving physical keys of
+/// type `P` to logical keys of type `L`.
+///
+/// Holding down a layer key activates the corresponding layer, which
+/// changes the meaning of other keys. If multiple layer keys are held,
+/// the layer activated last takes precedence. Keys not mapped by an
+/// active layer fall through to layers activated earlier and
+/// eventually to the base layer. Physical keys not mapped by any of
+/// them are ignored.
+///
+/// A physical key is resolved once, at the time it gets pressed. Its
+/// repeats and release are attributed to the same logical key, even if
+/// the active layer changed in the meantime. Multiple physical keys
+/// may resolve to the same logical key, which is then only considered
+/// released once all of them have been released. To that end, physical
+/// keys act as the devices of the underlying [`Keys`] object.
+///
+/// If the underlying [`Keys`] object releases a logical key on its own,
+/// e.g., because it was held for longer than
+/// [`Config::max_hold`] or because of an
+/// explicit [`Keys::release_all`], the physical keys holding it no
+/// longer resolve to it. Pressing them again resolves them afresh.
+#[derive(Clone, Debug)]
+pub struct Layers<P, L, I = Instant> {
+  /// The base layer.
+  base: HashMap<P, L>,
+  /// All known layers.
+  layers: Vec<Layer<P, L>>,
+  /// Indices of the active layers, in order of activation.
+  active: Vec<usize>,
+  /// The logical keys that physical keys held down resolved to.
+  resolved: HashMap<P, L>,
+  /// The underlying key state, in terms of logical keys held by
+  /// physical ones.
+  keys: Keys<L, I, P>,
+}
+
+impl<P, L, I> Layers<P, L, I>
+where
+  P: Clone + Eq + Hash,
+  L: Clone + Eq + Hash,
+  I: Timestamp,
+{
+  /// Create a new [`Layers`] object using `base` as the base layer and
+  /// tracking logical keys as per `config`.
+  pub fn new(base: HashMap<P, L>, config: Config) -> Self {
+    Self {
+      base,
+      layers: Vec::new(),
+      active: Vec::new(),
+      resolved: HashMap::new(),
+      keys: Keys::with_config(config),
+    }
+  }
+
+  /// Add a layer mapping physical to logical keys, activated while
+  /// `activator` is held down.
+  ///
+  /// Layer keys are never reported themselves.
+  pub fn add_layer(&mut self, activator: P, keys: HashMap<P, L>) {
+    let () = self.layers.push(Layer { activator, keys });
+  }
+
+  /// Retrieve the underlying [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<L, I, P> {
+    &self.keys
+  }
+
+  /// Retrieve the underlying [`Keys`] object mutably.
+  #[inline]
+  pub fn keys_mut(&mut self) -> &mut Keys<L, I, P> {
+    &mut self.keys
+  }
+
+  /// Resolve `key` to a logical key based on the currently active
+  /// layers.
+  pub fn resolve(&self, key: &P) -> Option<&L> {
+    self
+      .active
+      .iter()
+      .rev()
+      .find_map(|idx| self.layers[*idx].keys.get(key))
+      .or_else(|| self.base.get(key))
+  }
+
+  /// Retrieve the logical key that the held down physical `key`
+  /// resolved to when it got pressed.
+  ///
+  /// `None` is returned if the underlying [`Keys`] object released the
+  /// logical key in the meantime.
+  pub fn resolved(&self, key: &P) -> Option<&L> {
+    self
+      .resolved
+      .get(key)
+      .filter(|logical| self.keys.is_pressed_by(logical, key))
+  }
+
+  /// Find the layer activated by `key`, if any.
+  fn layer(&self, key: &P) -> Option<usize> {
+    self.layers.iter().position(|layer| layer.activator == *key)
+  }
+
+  /// This method is to be invoked on every physical key press
+  /// received.
+  pub fn on_key_press(&mut self, now: I, key: P) {
+    if let Some(idx) = self.layer(&key) {
+      if !self.active.contains(&idx) {
+        let () = self.active.push(idx);
+      }
+      return
+    }
+
+    if let Some(logical) = self.resolved(&key) {
+      // The key is already held down. Stick to what it resolved to
+      // originally.
+      let logical = logical.clone();
+      self.keys.on_device_key_press(now, key, logical)
+    } else if let Some(logical) = self.resolve(&key).cloned() {
+      let _prev = self.resolved.insert(key.clone(), logical.clone());
+      self.keys.on_device_key_press(now, key, logical)
+    }
+  }
+
+  /// This method is to be invoked on every auto-repeat generated by
+  /// the platform for a physical key.
+  ///
+  /// See [`Keys::on_key_repeat`].
+  pub fn on_key_repeat(&mut self, now: I, key: P) {
+    if self.layer(&key).is_some() {
+      return
+    }
+
+    if let Some(logical) = self.resolved(&key) {
+      let logical = logical.clone();
+      self.keys.on_device_key_repeat(now, key, logical)
+    } else {
+      let () = self.on_key_press(now, key);
+    }
+  }
+
+  /// This method is to be invoked on every physical key release
+  /// received.
+  pub fn on_key_release(&mut self, now: I, key: P) {
+    if let Some(idx) = self.layer(&key) {
+      let () = self.active.retain(|active| *active != idx);
+      return
+    }
+
+    if let Some(logical) = self.resolved.remove(&key) {
+      // If the logical key got released on our behalf already, there is
+      // nothing left to release.
+      if self.keys.is_pressed_by(&logical, &key) {
+        self.keys.on_device_key_release(now, key, logical)
+      }
+    }
+  }
+
+  /// Handle a "tick", invoking `handler` for each overdue press or
+  /// repeat of a logical key.
+  ///
+  /// See [`Keys::tick`].
+  #[inline]
+  pub fn tick<F, C>(&mut self, now: I, handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(&L, &mut KeyRepeat) -> C,
+    C: Default + BitOrAssign,
+  {
+    let result = self.keys.tick(now, handler);
+    // Forget about physical keys whose logical key got released on
+    // their behalf, e.g., because it was held down for too long.
+    let () = self
+      .resolved
+      .retain(|key, logical| self.keys.is_pressed_by(logical, key));
+    result
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::time::Duration;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+
+
+  /// Create a [`Layers`] object with a navigation layer on `Fn`.
+  fn layers() -> Layers<&'static str, &'static str> {
+    let base = HashMap::from([("h", "h"), ("j", "j"), ("x", "x"), ("Left", "left")]);
+    let nav = HashMap::from([("h", "left"), ("j", "down")]);
+    let mut layers = Layers::new(base, Config::new(5 * SECOND, SECOND));
+    let () = layers.add_layer("Fn", nav);
+    layers
+  }
+
+  /// Tick `layers` at `now`, collecting all logical keys reported.
+  fn collect(layers: &mut Layers<&'static str, &'static str>, now: Instant) -> Vec<&'static str> {
+    let mut reported = Vec::new();
+    let _result = layers.tick(now, |key, _repeat| {
+      let () = reported.push(*key);
+      true
+    });
+    let () = reported.sort();
+    reported
+  }
+
+
+  /// Check that keys are resolved based on the active layer, falling
+  /// through to the base layer.
+  #[test]
+  fn resolve() {
+    let now = Instant::now();
+    let mut layers = layers();
+
+    let () = layers.on_key_press(now, "Fn");
+    assert_eq!(layers.resolve(&"h"), Some(&"left"));
+    assert_eq!(layers.resolve(&"x"), Some(&"x"));
+    assert_eq!(layers.resolve(&"y"), None);
+
+    let () = layers.on_key_press(now, "h");
+    let () = layers.on_key_press(now, "x");
+    let () = layers.on_key_press(now, "y");
+    assert_eq!(collect(&mut layers, now), vec!["left", "x"]);
+
+    let () = layers.on_key_release(now, "Fn");
+    assert_eq!(layers.resolve(&"h"), Some(&"h"));
+  }
+
+
+  /// Check that a key keeps its logical meaning for its whole
+  /// lifetime.
+  #[test]
+  fn held_consistency() {
+    let now = Instant::now();
+    let mut layers = layers();
+
+    let () = layers.on_key_press(now, "Fn");
+    let () = layers.on_key_press(now, "j");
+    assert_eq!(collect(&mut layers, now), vec!["down"]);
+
+    // Releasing the layer key does not change the meaning of the key
+    // held down.
+    let () = layers.on_key_release(now + SECOND, "Fn");
+    assert_eq!(layers.resolved(&"j"), Some(&"down"));
+    let () = layers.on_key_repeat(now + 2 * SECOND, "j");
+    assert_eq!(collect(&mut layers, now + 5 * SECOND), vec!["down"]);
+    assert!(layers.keys().is_pressed(&"down"));
+
+    let () = layers.on_key_release(now + 5 * SECOND + SECOND / 2, "j");
+    assert!(!layers.keys().is_pressed(&"down"));
+    assert!(!layers.keys().is_pressed(&"j"));
+    assert_eq!(layers.resolved(&"j"), None);
+
+    // Once pressed again, the key resolves based on the base layer.
+    let () = layers.on_key_press(now + 6 * SECOND, "j");
+    assert_eq!(collect(&mut layers, now + 6 * SECOND), vec!["j"]);
+  }
+
+
+  /// Check that a logical key stays pressed for as long as any of the
+  /// physical keys resolving to it is held.
+  #[test]
+  fn shared_logical() {
+    let now = Instant::now();
+    let mut layers = layers();
+
+    let () = layers.on_key_press(now, "Fn");
+    let () = layers.on_key_press(now, "h");
+    let () = layers.on_key_press(now, "Left");
+    assert_eq!(layers.resolved(&"h"), Some(&"left"));
+    assert_eq!(layers.resolved(&"Left"), Some(&"left"));
+
+    let () = layers.on_key_release(now + SECOND, "Left");
+    assert!(layers.keys().is_pressed("left"));
+    let () = layers.on_key_release(now + 2 * SECOND, "h");
+    assert!(!layers.keys().is_pressed("left"));
+  }
+
+
+  /// Check that physical keys no longer resolve to logical keys that
+  /// the underlying [`Keys`] object released on its own.
+  #[test]
+  fn keys_release() {
+    let now = Instant::now();
+    let base = HashMap::from([("j", "j")]);
+    let nav = HashMap::from([("j", "down")]);
+    let mut config = Config::new(5 * SECOND, SECOND);
+    config.max_hold = Some(10 * SECOND);
+    let mut layers = Layers::new(base, config);
+    let () = layers.add_layer("Fn", nav);
+
+    let () = layers.on_key_press(now, "Fn");
+    let () = layers.on_key_press(now, "j");
+    assert_eq!(collect(&mut layers, now), vec!["down"]);
+
+    // The key is held for too long and gets released on our behalf.
+    let _reported = collect(&mut layers, now + 11 * SECOND);
+    assert!(!layers.keys().is_pressed("down"));
+    assert_eq!(layers.resolved(&"j"), None);
+
+    let () = layers.on_key_release(now + 12 * SECOND, "Fn");
+    let () = layers.on_key_press(now + 12 * SECOND, "j");
+    assert_eq!(layers.resolved(&"j"), Some(&"j"));
+    assert_eq!(collect(&mut layers, now + 12 * SECOND), vec!["j"]);
+    assert!(!layers.keys().is_pressed("down"));
+
+    // The same holds for explicit releases via the underlying object.
+    let () = layers.keys_mut().release_all(now + 13 * SECOND);
+    assert_eq!(layers.resolved(&"j"), None);
+    let () = layers.on_key_press(now + 14 * SECOND, "Fn");
+    let () = layers.on_key_press(now + 14 * SECOND, "j");
+    assert_eq!(layers.resolved(&"j"), Some(&"down"));
+    assert_eq!(collect(&mut layers, now + 14 * SECOND), vec!["down"]);
+  }
+}
@@ -17,7 +17,57 @@
 //! want to be able to influence these timings without having to make
 //! system-wide changes.
 
+mod analog;
+mod anomaly;
+mod bindings;
+mod buffer;
+mod calibrate;
+mod clock;
+mod config;
+mod frame;
+mod import;
 mod keys;
+mod layers;
+mod macros;
+mod queue;
+mod sequences;
+mod state;
+mod time;
 
+pub use analog::Analog;
+pub use analog::Directions;
+pub use analog::Hysteresis;
+pub use analog::Stick;
+pub use anomaly::Anomaly;
+pub use anomaly::Diagnostics;
+pub use bindings::Binding;
+pub use bindings::Bindings;
+pub use bindings::Keymap;
+pub use bindings::RepeatPolicy;
+pub use buffer::InputBuffer;
+pub use calibrate::Calibration;
+pub use clock::Clock;
+pub use clock::ClockedKeys;
+pub use clock::MockClock;
+pub use clock::SystemClock;
+pub use config::Config;
+pub use config::RepeatMode;
+pub use frame::Frame;
 pub use keys::KeyRepeat;
 pub use keys::Keys;
+pub use keys::Phase;
+pub use layers::Layers;
+pub use macros::Macro;
+pub use macros::MacroCancel;
+pub use macros::MacroEvent;
+pub use macros::MacroPlayer;
+pub use macros::MacroRecorder;
+pub use macros::MacroRepeat;
+pub use macros::Step;
+pub use queue::Queue;
+pub use sequences::Recognized;
+pub use sequences::Sequences;
+pub use state::Snapshot;
+pub use state::State;
+pub use time::FrameInstant;
+pub use time::Timestamp;
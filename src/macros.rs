@@ -0,0 +1,638 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for playing back timed sequences of key events.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::BitOrAssign;
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::Timestamp;
+
+
+/// The kinds of key events emitted during macro playback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum MacroEvent {
+  /// A key got pressed.
+  Press,
+  /// A key got released.
+  Release,
+}
+
+
+/// A single step of a [`Macro`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Step<K> {
+  /// Press the given key.
+  Press(K),
+  /// Release the given key.
+  Release(K),
+  /// Wait for the given duration before continuing.
+  Delay(Duration),
+}
+
+
+/// Whether a macro repeats while its trigger key is held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum MacroRepeat {
+  /// Play the macro once per press of the trigger key.
+  #[default]
+  Once,
+  /// Start the macro over for as long as the trigger key is held.
+  ///
+  /// Macros not containing any delays are never repeated.
+  WhileHeld,
+}
+
+
+/// How playback of a macro is cancelled once its trigger key is
+/// released.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum MacroCancel {
+  /// Finish the current playthrough of the macro, but do not start it
+  /// over.
+  #[default]
+  AfterCycle,
+  /// Stop playback as of the release, releasing all keys pressed by
+  /// the macro and not yet released.
+  Immediate,
+}
+
+
+/// A timed sequence of key events, bound to a trigger key in a
+/// [`MacroPlayer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[non_exhaustive]
+pub struct Macro<K> {
+  /// The steps making up the macro.
+  pub steps: Vec<Step<K>>,
+  /// Whether the macro repeats while its trigger key is held.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub repeat: MacroRepeat,
+  /// How playback is cancelled once the trigger key is released.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub cancel: MacroCancel,
+}
+
+impl<K> Macro<K> {
+  /// Create a new [`Macro`] consisting of the provided steps, played
+  /// once per press of the trigger key.
+  pub fn new(steps: Vec<Step<K>>) -> Self {
+    Self {
+      steps,
+      repeat: MacroRepeat::default(),
+      cancel: MacroCancel::default(),
+    }
+  }
+
+  /// Calculate the duration of a single playthrough of the macro.
+  fn duration(&self) -> Duration {
+    self
+      .steps
+      .iter()
+      .map(|step| match step {
+        Step::Delay(delay) => *delay,
+        Step::Press(..) | Step::Release(..) => Duration::ZERO,
+      })
+      .sum()
+  }
+}
+
+
+/// A type for recording key events into a [`Macro`].
+#[derive(Clone, Debug)]
+pub struct MacroRecorder<K, I = Instant> {
+  /// The steps recorded so far.
+  steps: Vec<Step<K>>,
+  /// The instant of the last event recorded.
+  last: Option<I>,
+}
+
+impl<K, I> MacroRecorder<K, I>
+where
+  I: Timestamp,
+{
+  /// Create a new, empty [`MacroRecorder`].
+  pub fn new() -> Self {
+    Self {
+      steps: Vec::new(),
+      last: None,
+    }
+  }
+
+  fn record(&mut self, now: I, step: Step<K>) {
+    if let Some(last) = self.last {
+      // Out-of-order events are treated as happening at the same time
+      // as the previous one.
+      let delay = now - last.min(now);
+      if !delay.is_zero() {
+        let () = self.steps.push(Step::Delay(delay));
+      }
+    }
+    self.last = self.last.max(Some(now));
+    let () = self.steps.push(step);
+  }
+
+  /// Record a press of `key` at `now`.
+  pub fn on_key_press(&mut self, now: I, key: K) {
+    self.record(now, Step::Press(key))
+  }
+
+  /// Record a release of `key` at `now`.
+  pub fn on_key_release(&mut self, now: I, key: K) {
+    self.record(now, Step::Release(key))
+  }
+
+  /// Finish recording, creating a [`Macro`] from the recorded events.
+  pub fn finish(self) -> Macro<K> {
+    Macro::new(self.steps)
+  }
+}
+
+impl<K, I> Default for MacroRecorder<K, I>
+where
+  I: Timestamp,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+
+/// The state of an ongoing macro playback.
+#[derive(Clone, Debug)]
+struct Playback<T, K, I> {
+  /// The key that triggered the playback.
+  trigger: T,
+  /// The index of the next step to play.
+  index: usize,
+  /// The instant at which the next step is due.
+  next_at: I,
+  /// The instant at which the trigger key got released, if it has
+  /// been.
+  released_at: Option<I>,
+  /// Keys pressed by the macro and not yet released.
+  held: Vec<K>,
+}
+
+
+/// A type for playing back [`Macro`]s bound to trigger keys.
+///
+/// Interaction follows the same pattern as with
+/// [`Keys`][crate::Keys]: presses and releases of trigger keys are
+/// reported via [`on_key_press`][MacroPlayer::on_key_press] and
+/// [`on_key_release`][MacroPlayer::on_key_release], after which the
+/// player should be [ticked][MacroPlayer::tick], which invokes a
+/// handler for all key events due and reports the instant at which
+/// the next "tick" should occur.
+///
+/// Trigger keys are of type `T`, while macros emit keys of type `K`.
+#[derive(Clone, Debug)]
+pub struct MacroPlayer<T, K, I = Instant> {
+  /// The macros bound to trigger keys.
+  macros: HashMap<T, Macro<K>>,
+  /// Ongoing playbacks, in order of their start.
+  playing: Vec<Playback<T, K, I>>,
+  /// Keys left pressed by stopped playbacks, to be released on the
+  /// next "tick", in order, along with the instant the release is due
+  /// at.
+  releasing: Vec<(K, I)>,
+  /// The instant of the last "tick".
+  last_tick: Option<I>,
+}
+
+impl<T, K, I> MacroPlayer<T, K, I>
+where
+  T: Clone + Eq + Hash,
+  K: Clone + Eq,
+  I: Timestamp,
+{
+  /// Create a new [`MacroPlayer`] without any macros bound.
+  pub fn new() -> Self {
+    Self {
+      macros: HashMap::new(),
+      playing: Vec::new(),
+      releasing: Vec::new(),
+      last_tick: None,
+    }
+  }
+
+  /// Stop all playbacks matching `stop`, scheduling the release of
+  /// all keys they left pressed in reverse order.
+  ///
+  /// The releases are due at the last "tick", as keys can only have
+  /// been pressed as part of one.
+  fn stop<P>(&mut self, mut stop: P)
+  where
+    P: FnMut(&Playback<T, K, I>) -> bool,
+  {
+    let releasing = &mut self.releasing;
+    let last_tick = self.last_tick;
+    let () = self.playing.retain_mut(|playback| {
+      if stop(playback) {
+        if let Some(at) = last_tick {
+          let () = releasing.extend(playback.held.drain(..).rev().map(|key| (key, at)));
+        }
+        false
+      } else {
+        true
+      }
+    });
+  }
+
+  /// Bind `macro_` to `trigger`, returning the macro previously bound
+  /// to it, if any.
+  ///
+  /// Ongoing playback of the previously bound macro, if any, is
+  /// stopped. Keys it pressed and did not release yet are released on
+  /// the next [`tick`][MacroPlayer::tick], in reverse order.
+  pub fn bind(&mut self, trigger: T, macro_: Macro<K>) -> Option<Macro<K>> {
+    let () = self.stop(|playback| playback.trigger == trigger);
+    self.macros.insert(trigger, macro_)
+  }
+
+  /// Remove the macro bound to `trigger`, if any.
+  ///
+  /// Ongoing playback of the macro, if any, is stopped. Keys it pressed
+  /// and did not release yet are released on the next
+  /// [`tick`][MacroPlayer::tick], in reverse order.
+  pub fn unbind(&mut self, trigger: &T) -> Option<Macro<K>> {
+    let () = self.stop(|playback| playback.trigger == *trigger);
+    self.macros.remove(trigger)
+  }
+
+  /// This method is to be invoked on every press of a potential
+  /// trigger key.
+  ///
+  /// Returns `true` if a macro is bound to `trigger`, in which case
+  /// the press is likely not to be processed any further.
+  pub fn on_key_press(&mut self, now: I, trigger: &T) -> bool {
+    if !self.macros.contains_key(trigger) {
+      return false
+    }
+
+    if let Some(playback) = self
+      .playing
+      .iter_mut()
+      .find(|playback| playback.trigger == *trigger)
+    {
+      // The macro is still being played back from an earlier press.
+      // Just mark the trigger as being held again.
+      playback.released_at = None;
+    } else {
+      let () = self.playing.push(Playback {
+        trigger: trigger.clone(),
+        index: 0,
+        next_at: now,
+        released_at: None,
+        held: Vec::new(),
+      });
+    }
+    true
+  }
+
+  /// This method is to be invoked on every release of a potential
+  /// trigger key.
+  ///
+  /// Returns `true` if a macro is bound to `trigger`.
+  pub fn on_key_release(&mut self, now: I, trigger: &T) -> bool {
+    if let Some(playback) = self
+      .playing
+      .iter_mut()
+      .find(|playback| playback.trigger == *trigger)
+    {
+      if playback.released_at.is_none() {
+        playback.released_at = Some(now);
+      }
+    }
+    self.macros.contains_key(trigger)
+  }
+
+  /// Check whether the macro bound to `trigger` is currently being
+  /// played back.
+  pub fn is_playing(&self, trigger: &T) -> bool {
+    self
+      .playing
+      .iter()
+      .any(|playback| playback.trigger == *trigger)
+  }
+
+  /// Handle a "tick", i.e., play back all macro steps due as of `now`,
+  /// invoking `handler` for each key event.
+  ///
+  /// Besides the key and event, `handler` is provided with the instant
+  /// the event was due at, which may lie before `now` if the "tick"
+  /// happened late. That allows for feeding the event into a
+  /// [`Keys`][crate::Keys] object with accurate timing.
+  ///
+  /// Similar to [`Keys::tick`][crate::Keys::tick], `handler` may
+  /// return any kind of state that can be bitwise ORed. In addition,
+  /// the instant at which the next "tick" should occur is returned as
+  /// well (if any).
+  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(&K, MacroEvent, I) -> C,
+    C: Default + BitOrAssign,
+  {
+    let mut change = C::default();
+    let mut next_tick = None::<I>;
+    self.last_tick = self.last_tick.max(Some(now));
+
+    for (key, at) in self.releasing.drain(..) {
+      change |= handler(&key, MacroEvent::Release, at);
+    }
+
+    let () = self.playing.retain_mut(|playback| {
+      let macro_ = &self.macros[&playback.trigger];
+      let cancel = match macro_.cancel {
+        MacroCancel::Immediate => playback.released_at,
+        MacroCancel::AfterCycle => None,
+      };
+      // Steps due after a cancelling release are never played.
+      let until = cancel.map_or(now, |at| at.min(now));
+
+      loop {
+        if playback.next_at > until {
+          break
+        }
+
+        if let Some(step) = macro_.steps.get(playback.index) {
+          playback.index += 1;
+          match step {
+            Step::Press(key) => {
+              change |= handler(key, MacroEvent::Press, playback.next_at);
+              let () = playback.held.push(key.clone());
+            },
+            Step::Release(key) => {
+              change |= handler(key, MacroEvent::Release, playback.next_at);
+              if let Some(idx) = playback.held.iter().position(|held| held == key) {
+                let _key = playback.held.remove(idx);
+              }
+            },
+            Step::Delay(delay) => playback.next_at += *delay,
+          }
+        } else {
+          let held = playback.released_at.is_none_or(|at| playback.next_at < at);
+          let repeat = macro_.repeat == MacroRepeat::WhileHeld && !macro_.duration().is_zero();
+          if held && repeat {
+            playback.index = 0;
+          } else {
+            return false
+          }
+        }
+      }
+
+      let mut next_at = playback.next_at;
+      if let Some(at) = cancel {
+        if at <= now {
+          // Release whatever the macro left pressed, in reverse order.
+          for key in playback.held.drain(..).rev() {
+            change |= handler(&key, MacroEvent::Release, at);
+          }
+          return false
+        }
+        next_at = next_at.min(at);
+      }
+
+      next_tick = Some(next_tick.map_or(next_at, |next| next.min(next_at)));
+      true
+    });
+
+    (change, next_tick)
+  }
+
+  /// Stop all ongoing playbacks.
+  ///
+  /// Keys pressed by the macros and not yet released are released on
+  /// the next [`tick`][MacroPlayer::tick], in reverse order. No other
+  /// events are emitted.
+  #[inline]
+  pub fn clear(&mut self) {
+    self.stop(|_playback| true)
+  }
+}
+
+impl<T, K, I> Default for MacroPlayer<T, K, I>
+where
+  T: Clone + Eq + Hash,
+  K: Clone + Eq,
+  I: Timestamp,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  const MS: Duration = Duration::from_millis(1);
+
+
+  /// Play back `player` until no more steps are due, ticking it at
+  /// the reported instants and at `until` at the latest.
+  fn play<T>(
+    player: &mut MacroPlayer<T, char>,
+    now: Instant,
+    until: Instant,
+  ) -> Vec<(u128, char, MacroEvent)>
+  where
+    T: Clone + Eq + Hash,
+  {
+    let mut events = Vec::new();
+    let mut tick = Some(now);
+    while let Some(at) = tick.filter(|at| *at <= until) {
+      let (_changed, next) = player.tick(at, |key, event, due| {
+        let () = events.push(((due - now).as_millis(), *key, event));
+        true
+      });
+      tick = next;
+    }
+    events
+  }
+
+
+  /// Check that a scripted macro gets played back with the expected
+  /// timing.
+  #[test]
+  fn scripted_playback() {
+    let now = Instant::now();
+    let mut player = MacroPlayer::<char, char>::new();
+    let macro_ = Macro::new(vec![
+      Step::Press('a'),
+      Step::Delay(10 * MS),
+      Step::Release('a'),
+      Step::Press('b'),
+      Step::Delay(5 * MS),
+      Step::Release('b'),
+    ]);
+    let _prev = player.bind('m', macro_);
+
+    assert!(!player.on_key_press(now, &'x'));
+    assert!(player.on_key_press(now, &'m'));
+    // With the default settings, a release does not affect playback.
+    assert!(player.on_key_release(now + MS, &'m'));
+
+    let events = play(&mut player, now, now + 100 * MS);
+    let expected = vec![
+      (0, 'a', MacroEvent::Press),
+      (10, 'a', MacroEvent::Release),
+      (10, 'b', MacroEvent::Press),
+      (15, 'b', MacroEvent::Release),
+    ];
+    assert_eq!(events, expected);
+    assert!(!player.is_playing(&'m'));
+  }
+
+
+  /// Check that events report the instant they were due at when the
+  /// player is ticked late.
+  #[test]
+  fn late_tick() {
+    let now = Instant::now();
+    let mut player = MacroPlayer::<char, char>::new();
+    let macro_ = Macro::new(vec![
+      Step::Press('a'),
+      Step::Delay(10 * MS),
+      Step::Release('a'),
+    ]);
+    let _prev = player.bind('m', macro_);
+
+    assert!(player.on_key_press(now, &'m'));
+    let mut events = Vec::new();
+    let (_changed, next) = player.tick(now + 100 * MS, |key, event, due| {
+      let () = events.push(((due - now).as_millis(), *key, event));
+      true
+    });
+    assert_eq!(next, None);
+    assert_eq!(
+      events,
+      vec![(0, 'a', MacroEvent::Press), (10, 'a', MacroEvent::Release)]
+    );
+  }
+
+
+  /// Check that a macro repeats while its trigger is held and gets
+  /// cancelled as configured.
+  #[test]
+  fn repeat_cancel() {
+    let now = Instant::now();
+    let mut player = MacroPlayer::<char, char>::new();
+    let mut macro_ = Macro::new(vec![
+      Step::Press('a'),
+      Step::Delay(10 * MS),
+      Step::Release('a'),
+      Step::Delay(10 * MS),
+    ]);
+    macro_.repeat = MacroRepeat::WhileHeld;
+    macro_.cancel = MacroCancel::Immediate;
+    let _prev = player.bind('m', macro_);
+
+    assert!(player.on_key_press(now, &'m'));
+    assert!(player.on_key_release(now + 25 * MS, &'m'));
+
+    let events = play(&mut player, now, now + 100 * MS);
+    let expected = vec![
+      (0, 'a', MacroEvent::Press),
+      (10, 'a', MacroEvent::Release),
+      (20, 'a', MacroEvent::Press),
+      // The key pressed by the macro is released on cancellation.
+      (25, 'a', MacroEvent::Release),
+    ];
+    assert_eq!(events, expected);
+    assert!(!player.is_playing(&'m'));
+  }
+
+
+  /// Check that keys pressed by a macro are released once its playback
+  /// gets stopped.
+  #[test]
+  fn stop_release() {
+    let now = Instant::now();
+    let mut player = MacroPlayer::<char, char>::new();
+    let macro_ = Macro::new(vec![
+      Step::Press('a'),
+      Step::Press('b'),
+      Step::Delay(10 * MS),
+      Step::Release('b'),
+      Step::Release('a'),
+    ]);
+    let _prev = player.bind('m', macro_.clone());
+    let _prev = player.bind('n', macro_);
+
+    let mut events = Vec::new();
+    let mut handler = |key: &char, event, _due| {
+      let () = events.push((*key, event));
+      true
+    };
+
+    assert!(player.on_key_press(now, &'m'));
+    let (_changed, next) = player.tick(now, &mut handler);
+    assert_eq!(next, Some(now + 10 * MS));
+
+    // Rebinding the trigger stops playback, releasing the keys.
+    let _prev = player.bind('m', Macro::new(Vec::new()));
+    assert!(!player.is_playing(&'m'));
+    let (_changed, next) = player.tick(now + MS, &mut handler);
+    assert_eq!(next, None);
+
+    assert!(player.on_key_press(now + MS, &'n'));
+    let (_changed, _next) = player.tick(now + MS, &mut handler);
+    let () = player.clear();
+    let (_changed, next) = player.tick(now + 2 * MS, &mut handler);
+    assert_eq!(next, None);
+
+    assert_eq!(
+      events,
+      vec![
+        ('a', MacroEvent::Press),
+        ('b', MacroEvent::Press),
+        ('b', MacroEvent::Release),
+        ('a', MacroEvent::Release),
+        ('a', MacroEvent::Press),
+        ('b', MacroEvent::Press),
+        ('b', MacroEvent::Release),
+        ('a', MacroEvent::Release),
+      ]
+    );
+  }
+
+
+  /// Check that we can record a macro.
+  #[test]
+  fn record() {
+    let now = Instant::now();
+    let mut recorder = MacroRecorder::new();
+    let () = recorder.on_key_press(now, 'a');
+    let () = recorder.on_key_press(now, 'b');
+    let () = recorder.on_key_release(now + 20 * MS, 'a');
+    let () = recorder.on_key_release(now + 30 * MS, 'b');
+
+    let macro_ = recorder.finish();
+    let expected = vec![
+      Step::Press('a'),
+      Step::Press('b'),
+      Step::Delay(20 * MS),
+      Step::Release('a'),
+      Step::Delay(10 * MS),
+      Step::Release('b'),
+    ];
+    assert_eq!(macro_.steps, expected);
+    assert_eq!(macro_.duration(), 30 * MS);
+  }
+}
@@ -0,0 +1,114 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for queuing synthetic input from within a handler.
+
+use std::vec::Drain;
+
+use crate::keys::KeyEvent;
+
+
+/// A single queued key event.
+#[derive(Clone, Debug)]
+pub(crate) struct Queued<K, I, D> {
+  /// The instant at which the event happened.
+  pub at: I,
+  /// The device the event is attributed to.
+  pub device: D,
+  /// The key in question.
+  pub key: K,
+  /// The kind of event.
+  pub event: KeyEvent,
+}
+
+
+/// A queue of synthetic key events, as passed to the handler by
+/// [`Keys::tick_queued`][crate::Keys::tick_queued].
+///
+/// Queued events are applied once the "tick" has been handled, in the
+/// order in which they were queued. They are subject to the same
+/// treatment as events reported directly, e.g., events with an instant
+/// earlier than the "tick" are treated as happening at the time of the
+/// "tick".
+#[derive(Clone, Debug)]
+pub struct Queue<K, I, D = ()> {
+  /// The queued events, in order.
+  events: Vec<Queued<K, I, D>>,
+}
+
+impl<K, I, D> Queue<K, I, D> {
+  fn push(&mut self, at: I, device: D, key: K, event: KeyEvent) {
+    let () = self.events.push(Queued {
+      at,
+      device,
+      key,
+      event,
+    });
+  }
+
+  /// Queue a press of `key` at `at`, attributed to the default device.
+  pub fn press(&mut self, at: I, key: K)
+  where
+    D: Default,
+  {
+    self.push(at, D::default(), key, KeyEvent::Press)
+  }
+
+  /// Queue a platform generated repeat of `key` at `at`, attributed to
+  /// the default device.
+  pub fn repeat(&mut self, at: I, key: K)
+  where
+    D: Default,
+  {
+    self.push(at, D::default(), key, KeyEvent::Repeat)
+  }
+
+  /// Queue a release of `key` at `at`, attributed to the default
+  /// device.
+  pub fn release(&mut self, at: I, key: K)
+  where
+    D: Default,
+  {
+    self.push(at, D::default(), key, KeyEvent::Release)
+  }
+
+  /// Queue a press of `key` at `at`, attributed to `device`.
+  pub fn device_press(&mut self, at: I, device: D, key: K) {
+    self.push(at, device, key, KeyEvent::Press)
+  }
+
+  /// Queue a platform generated repeat of `key` at `at`, attributed to
+  /// `device`.
+  pub fn device_repeat(&mut self, at: I, device: D, key: K) {
+    self.push(at, device, key, KeyEvent::Repeat)
+  }
+
+  /// Queue a release of `key` at `at`, attributed to `device`.
+  pub fn device_release(&mut self, at: I, device: D, key: K) {
+    self.push(at, device, key, KeyEvent::Release)
+  }
+
+  /// Retrieve the number of queued events.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.events.len()
+  }
+
+  /// Check whether no events are queued.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.events.is_empty()
+  }
+
+  /// Remove all queued events, in order.
+  #[inline]
+  pub(crate) fn drain(&mut self) -> Drain<'_, Queued<K, I, D>> {
+    self.events.drain(..)
+  }
+}
+
+impl<K, I, D> Default for Queue<K, I, D> {
+  fn default() -> Self {
+    Self { events: Vec::new() }
+  }
+}
@@ -0,0 +1,616 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for recognizing sequences of key presses.
+
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::ops::BitOrAssign;
+use std::slice;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::frame::Fire;
+use crate::Config;
+use crate::Keys;
+use crate::Timestamp;
+
+
+/// A sequence of keys bound to an action.
+#[derive(Clone, Debug)]
+struct Sequence<K, A> {
+  /// The keys making up the sequence.
+  keys: Vec<K>,
+  /// The action triggered.
+  action: A,
+  /// Whether repeats of the final key re-trigger the action.
+  repeat: bool,
+}
+
+
+/// The result of matching pending keys against known sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Matched {
+  /// The keys do not form the beginning of any sequence.
+  None,
+  /// The keys form the beginning of at least one sequence.
+  Prefix,
+  /// The keys form a complete sequence, with the given index, and
+  /// possibly the beginning of longer ones.
+  Exact(usize),
+}
+
+
+/// An event pending delivery to the handler.
+#[derive(Clone, Debug)]
+enum Output<K> {
+  /// The sequence with the given index got completed.
+  Action(usize),
+  /// The given keys did not form any sequence.
+  Keys(Vec<K>),
+}
+
+
+/// A recognized event, as reported to the handler by
+/// [`Sequences::tick`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Recognized<'seq, K, A> {
+  /// A sequence bound to the given action got completed.
+  Action(&'seq A),
+  /// The given keys, which do not form any sequence, were pressed or
+  /// repeated.
+  Keys(&'seq [K]),
+}
+
+
+/// A recognizer for sequences of key presses on top of [`Keys`], such
+/// as leader keys or multi-key shortcuts.
+///
+/// Subsequent keys of a sequence have to be pressed within a timeout
+/// of each other. If the keys pressed so far form a complete sequence
+/// that is also the beginning of a longer one, the action of the
+/// shorter sequence is only triggered once the timeout passed without
+/// the longer sequence being continued. Keys not forming any sequence
+/// are reported as such.
+///
+/// Recognized events are reported on the next [`tick`][Sequences::tick],
+/// along with repeats of keys as determined by the underlying
+/// [`Keys`] object.
+#[derive(Clone, Debug)]
+pub struct Sequences<K, A, I = Instant> {
+  /// The maximum time between subsequent keys of a sequence.
+  timeout: Duration,
+  /// The known sequences.
+  sequences: Vec<Sequence<K, A>>,
+  /// The keys of the sequence currently being entered.
+  pending: Vec<K>,
+  /// The instant before which the next key of the pending sequence
+  /// has to be pressed.
+  deadline: Option<I>,
+  /// The final keys of completed sequences that are still tracked by
+  /// the underlying [`Keys`] object, along with the sequence's index if
+  /// its action can be re-triggered.
+  completed: Vec<(K, Option<usize>)>,
+  /// Events pending delivery to the handler.
+  output: Vec<Output<K>>,
+  /// The underlying key state.
+  keys: Keys<K, I>,
+}
+
+impl<K, A, I> Sequences<K, A, I>
+where
+  K: Clone + Eq + Hash,
+  I: Timestamp,
+{
+  /// Create a new [`Sequences`] object, with subsequent keys of a
+  /// sequence having to be pressed within `timeout` and repeats being
+  /// generated as per `config`.
+  pub fn new(timeout: Duration, config: Config) -> Self {
+    Self {
+      timeout,
+      sequences: Vec::new(),
+      pending: Vec::new(),
+      deadline: None,
+      completed: Vec::new(),
+      output: Vec::new(),
+      keys: Keys::with_config(config),
+    }
+  }
+
+  /// Bind `action` to the sequence of `keys`.
+  ///
+  /// If `repeat` is `true`, repeats of the final key of the sequence
+  /// re-trigger the action. Binding an empty sequence has no effect.
+  pub fn bind(&mut self, keys: Vec<K>, action: A, repeat: bool) {
+    if keys.is_empty() {
+      return
+    }
+
+    let () = self.unbind(&keys);
+    let () = self.sequences.push(Sequence {
+      keys,
+      action,
+      repeat,
+    });
+  }
+
+  /// Remove the sequence of `keys`, if bound.
+  ///
+  /// Completions of the sequence not yet reported are dropped.
+  pub fn unbind(&mut self, keys: &[K]) {
+    let removed = match self
+      .sequences
+      .iter()
+      .position(|sequence| sequence.keys == keys)
+    {
+      Some(removed) => removed,
+      None => return,
+    };
+    let _sequence = self.sequences.remove(removed);
+
+    // Indices of subsequent sequences shift down by one, so adjust
+    // everything referring to them.
+    let adjust = |idx: usize| match idx {
+      _ if idx < removed => Some(idx),
+      _ if idx > removed => Some(idx - 1),
+      _ => None,
+    };
+    let () = self.output.retain_mut(|output| match output {
+      Output::Action(idx) => adjust(*idx).map(|new| *idx = new).is_some(),
+      Output::Keys(..) => true,
+    });
+    let () = self
+      .completed
+      .iter_mut()
+      .for_each(|(_last, idx)| *idx = idx.and_then(adjust));
+  }
+
+  /// Retrieve the keys of the sequence currently being entered, e.g.,
+  /// for display purposes.
+  #[inline]
+  pub fn pending(&self) -> &[K] {
+    &self.pending
+  }
+
+  /// Retrieve the underlying [`Keys`] object.
+  #[inline]
+  pub fn keys(&self) -> &Keys<K, I> {
+    &self.keys
+  }
+
+  /// Match `keys` against all known sequences.
+  fn matched(&self, keys: &[K]) -> Matched {
+    let mut matched = Matched::None;
+    for (idx, sequence) in self.sequences.iter().enumerate() {
+      if sequence.keys == keys {
+        return Matched::Exact(idx)
+      }
+      if sequence.keys.starts_with(keys) {
+        matched = Matched::Prefix;
+      }
+    }
+    matched
+  }
+
+  /// Resolve the pending sequence, e.g., because it timed out.
+  fn flush(&mut self) {
+    if !self.pending.is_empty() {
+      match self.matched(&self.pending) {
+        Matched::Exact(idx) => {
+          let () = self.complete(idx);
+          let () = self.pending.clear();
+        },
+        Matched::Prefix | Matched::None => {
+          let keys = self.pending.drain(..).collect();
+          let () = self.output.push(Output::Keys(keys));
+        },
+      }
+    }
+    self.deadline = None;
+  }
+
+  /// Take note of the sequence with index `idx` having been completed.
+  fn complete(&mut self, idx: usize) {
+    let sequence = &self.sequences[idx];
+    let retrigger = sequence.repeat.then_some(idx);
+    if let Some(last) = sequence.keys.last() {
+      let () = self.completed.retain(|(key, _idx)| key != last);
+      let () = self.completed.push((last.clone(), retrigger));
+    }
+    let () = self.output.push(Output::Action(idx));
+  }
+
+  /// Check whether the pending sequence timed out at `now`.
+  fn expired(&self, now: I) -> bool {
+    self.deadline.is_some_and(|deadline| deadline <= now)
+  }
+
+  /// Feed the press of `key` at `now` to the recognizer.
+  fn feed(&mut self, now: I, key: K) {
+    if self.expired(now) {
+      let () = self.flush();
+    }
+
+    let () = self.completed.retain(|(last, _idx)| *last != key);
+    let () = self.pending.push(key);
+
+    loop {
+      match self.matched(&self.pending) {
+        Matched::Exact(idx) => {
+          let ambiguous = self.sequences.iter().any(|sequence| {
+            sequence.keys.len() > self.pending.len() && sequence.keys.starts_with(&self.pending)
+          });
+          if ambiguous {
+            self.deadline = Some(now + self.timeout);
+          } else {
+            let () = self.complete(idx);
+            let () = self.pending.clear();
+            self.deadline = None;
+          }
+          break
+        },
+        Matched::Prefix => {
+          self.deadline = Some(now + self.timeout);
+          break
+        },
+        Matched::None => {
+          let len = self.pending.len();
+          if len == 1 {
+            let keys = self.pending.drain(..).collect();
+            let () = self.output.push(Output::Keys(keys));
+            self.deadline = None;
+            break
+          }
+
+          // If the keys before the latest one completed a sequence,
+          // that is what the user meant. Otherwise fall back to the
+          // longest tail still forming the beginning of a sequence.
+          if let Matched::Exact(idx) = self.matched(&self.pending[..len - 1]) {
+            let () = self.complete(idx);
+            let _keys = self.pending.drain(..len - 1);
+          } else {
+            let start = (1..len)
+              .find(|start| self.matched(&self.pending[*start..]) != Matched::None)
+              .unwrap_or(len);
+            let keys = self.pending.drain(..start).collect();
+            let () = self.output.push(Output::Keys(keys));
+            if self.pending.is_empty() {
+              self.deadline = None;
+              break
+            }
+          }
+        },
+      }
+    }
+  }
+
+  /// This method is to be invoked on every key press received.
+  pub fn on_key_press(&mut self, now: I, key: K) {
+    if !self.keys.is_pressed(&key) {
+      let () = self.feed(now, key.clone());
+    }
+    self.keys.on_key_press(now, key)
+  }
+
+  /// This method is to be invoked on every key release received.
+  pub fn on_key_release(&mut self, now: I, key: K) {
+    self.keys.on_key_release(now, key)
+  }
+
+  /// Handle a "tick", invoking `handler` for each event recognized.
+  ///
+  /// Similar to [`Keys::tick`], `handler` may return any kind of state
+  /// that can be bitwise ORed. In addition, the instant at which the
+  /// next "tick" should occur is returned as well (if any), taking
+  /// into account the timeout of a pending sequence.
+  pub fn tick<F, C>(&mut self, now: I, mut handler: F) -> (C, Option<I>)
+  where
+    F: FnMut(Recognized<'_, K, A>) -> C,
+    C: Default + BitOrAssign,
+  {
+    if self.expired(now) {
+      let () = self.flush();
+    }
+
+    let mut change = C::default();
+    for output in self.output.drain(..) {
+      change |= match output {
+        Output::Action(idx) => handler(Recognized::Action(&self.sequences[idx].action)),
+        Output::Keys(keys) => handler(Recognized::Keys(&keys)),
+      };
+    }
+
+    let sequences = &self.sequences;
+    let pending = &self.pending;
+    let completed = &self.completed;
+    let mut removed = Vec::new();
+    let result = self.keys.tick_with(
+      now,
+      |key, fire, _repeat| {
+        // Presses have been handled already.
+        if fire == Fire::Press {
+          return Ok::<_, Infallible>(C::default())
+        }
+
+        let completed = completed.iter().find(|(last, _idx)| last == key);
+        let result = match completed {
+          Some((_last, Some(idx))) => handler(Recognized::Action(&sequences[*idx].action)),
+          // Repeats of the final key of a sequence not re-triggering
+          // its action are swallowed.
+          Some((_last, None)) => C::default(),
+          // Repeats of keys that are part of a sequence being entered
+          // are ignored.
+          None if pending.contains(key) => C::default(),
+          None => handler(Recognized::Keys(slice::from_ref(key))),
+        };
+        Ok(result)
+      },
+      |key| removed.push(key.clone()),
+    );
+    let () = self
+      .completed
+      .retain(|(last, _idx)| !removed.contains(last));
+    let (other, next_tick) = match result {
+      Ok(result) => result,
+      Err(err) => match err {},
+    };
+    change |= other;
+
+    let next_tick = match (next_tick, self.deadline) {
+      (Some(next), Some(deadline)) => Some(next.min(deadline)),
+      (next, deadline) => next.or(deadline),
+    };
+    (change, next_tick)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::cell::Cell;
+  use std::cell::RefCell;
+
+
+  const SECOND: Duration = Duration::from_secs(1);
+
+
+  /// Tick `sequences` at `now`, collecting everything recognized.
+  fn collect(sequences: &mut Sequences<&'static str, u8>, now: Instant) -> Vec<String> {
+    let mut recognized = Vec::new();
+    let _result = sequences.tick(now, |event| {
+      let event = match event {
+        Recognized::Action(action) => format!("#{action}"),
+        Recognized::Keys(keys) => keys.join(" "),
+      };
+      let () = recognized.push(event);
+      true
+    });
+    recognized
+  }
+
+  /// Create a [`Sequences`] object with a set of partly overlapping
+  /// sequences bound.
+  fn sequences() -> Sequences<&'static str, u8> {
+    let mut sequences = Sequences::new(SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["C-x", "C-s"], 1, false);
+    let () = sequences.bind(vec!["C-x", "C-c"], 2, false);
+    let () = sequences.bind(vec!["g"], 3, false);
+    let () = sequences.bind(vec!["g", "g"], 4, false);
+    let () = sequences.bind(vec!["C-x", "o"], 5, true);
+    sequences
+  }
+
+
+  /// Check that multi-key sequences are recognized.
+  #[test]
+  fn multi_key() {
+    let now = Instant::now();
+    let mut sequences = sequences();
+
+    let () = sequences.on_key_press(now, "C-x");
+    assert_eq!(sequences.pending(), &["C-x"]);
+    let () = sequences.on_key_release(now, "C-x");
+    let () = sequences.on_key_press(now + SECOND / 2, "C-s");
+    let () = sequences.on_key_release(now + SECOND / 2, "C-s");
+    assert_eq!(sequences.pending(), &[] as &[&str]);
+    assert_eq!(collect(&mut sequences, now + SECOND / 2), vec!["#1"]);
+
+    // Keys not forming any sequence are reported as such.
+    let () = sequences.on_key_press(now + 2 * SECOND, "C-x");
+    let () = sequences.on_key_release(now + 2 * SECOND, "C-x");
+    let () = sequences.on_key_press(now + 2 * SECOND, "a");
+    let () = sequences.on_key_release(now + 2 * SECOND, "a");
+    assert_eq!(collect(&mut sequences, now + 2 * SECOND), vec!["C-x a"]);
+
+    // Pressing the next key too late breaks the sequence.
+    let () = sequences.on_key_press(now + 3 * SECOND, "C-x");
+    let () = sequences.on_key_release(now + 3 * SECOND, "C-x");
+    let (_, next) = sequences.tick(now + 3 * SECOND, |_event| true);
+    assert_eq!(next, Some(now + 4 * SECOND));
+    let () = sequences.on_key_press(now + 5 * SECOND, "C-c");
+    assert_eq!(
+      collect(&mut sequences, now + 5 * SECOND),
+      vec!["C-x", "C-c"]
+    );
+  }
+
+
+  /// Check that ambiguous prefixes are resolved as expected.
+  #[test]
+  fn ambiguity() {
+    let now = Instant::now();
+    let mut sequences = sequences();
+
+    let () = sequences.on_key_press(now, "g");
+    let () = sequences.on_key_release(now, "g");
+    assert_eq!(collect(&mut sequences, now), Vec::<String>::new());
+    assert_eq!(collect(&mut sequences, now + SECOND), vec!["#3"]);
+
+    let () = sequences.on_key_press(now + 2 * SECOND, "g");
+    let () = sequences.on_key_release(now + 2 * SECOND, "g");
+    let () = sequences.on_key_press(now + 2 * SECOND, "g");
+    assert_eq!(collect(&mut sequences, now + 2 * SECOND), vec!["#4"]);
+    let () = sequences.on_key_release(now + 2 * SECOND, "g");
+
+    // A key not continuing the longer sequence completes the shorter
+    // one.
+    let () = sequences.on_key_press(now + 4 * SECOND, "g");
+    let () = sequences.on_key_press(now + 4 * SECOND, "C-x");
+    let () = sequences.on_key_press(now + 4 * SECOND, "C-s");
+    assert_eq!(collect(&mut sequences, now + 4 * SECOND), vec!["#3", "#1"]);
+  }
+
+
+  /// Check that repeats of the final key re-trigger an action, if
+  /// desired.
+  #[test]
+  fn retrigger() {
+    let now = Instant::now();
+    let mut sequences = sequences();
+
+    let () = sequences.on_key_press(now, "C-x");
+    let () = sequences.on_key_release(now, "C-x");
+    let () = sequences.on_key_press(now, "o");
+    assert_eq!(collect(&mut sequences, now), vec!["#5"]);
+    assert_eq!(collect(&mut sequences, now + 6 * SECOND), vec!["#5", "#5"]);
+    let () = sequences.on_key_release(now + 6 * SECOND, "o");
+
+    // Repeats of keys not part of any sequence are reported as keys.
+    let () = sequences.on_key_press(now + 10 * SECOND, "a");
+    assert_eq!(collect(&mut sequences, now + 15 * SECOND), vec!["a", "a"]);
+  }
+
+
+  /// Check that repeats of the final key of a sequence not
+  /// re-triggering its action are swallowed.
+  #[test]
+  fn no_retrigger() {
+    let now = Instant::now();
+    let actions = Cell::new(0);
+    let keys = Cell::new(0);
+    let mut sequences = Sequences::new(SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["C-x", "C-s"], (), false);
+
+    let mut handler = |event: Recognized<'_, _, _>| {
+      match event {
+        Recognized::Action(()) => actions.set(actions.get() + 1),
+        Recognized::Keys(..) => keys.set(keys.get() + 1),
+      }
+      true
+    };
+
+    let () = sequences.on_key_press(now, "C-x");
+    let () = sequences.on_key_release(now, "C-x");
+    let () = sequences.on_key_press(now, "C-s");
+    let _result = sequences.tick(now, &mut handler);
+    let _result = sequences.tick(now + 7 * SECOND, &mut handler);
+    assert_eq!(actions.get(), 1);
+    assert_eq!(keys.get(), 0);
+  }
+
+
+  /// Check that unbinding a sequence preserves completions of other
+  /// sequences not yet reported.
+  #[test]
+  fn unbind_pending() {
+    let now = Instant::now();
+    let mut sequences = Sequences::new(SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["a"], 1, false);
+    let () = sequences.bind(vec!["b"], 2, false);
+    let () = sequences.bind(vec!["c"], 3, false);
+
+    let () = sequences.on_key_press(now, "a");
+    let () = sequences.on_key_press(now, "b");
+    let () = sequences.on_key_press(now, "c");
+    let () = sequences.unbind(&["b"]);
+    let () = sequences.bind(vec!["d"], 4, false);
+
+    let recognized = RefCell::new(Vec::new());
+    let _result = sequences.tick(now, |event| {
+      if let Recognized::Action(action) = event {
+        let () = recognized.borrow_mut().push(*action);
+      }
+      true
+    });
+    assert_eq!(recognized.into_inner(), vec![1, 3]);
+  }
+
+
+  /// Check that the next key of a sequence is too late when pressed
+  /// right at the timeout.
+  #[test]
+  fn timeout_boundary() {
+    let now = Instant::now();
+    let actions = Cell::new(0);
+    let mut sequences = Sequences::new(SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["C-x", "C-s"], (), false);
+
+    let () = sequences.on_key_press(now, "C-x");
+    let () = sequences.on_key_release(now, "C-x");
+    let () = sequences.on_key_press(now + SECOND, "C-s");
+    let _result = sequences.tick(now + SECOND, |event| {
+      if let Recognized::Action(()) = event {
+        let () = actions.set(actions.get() + 1);
+      }
+      true
+    });
+    assert_eq!(actions.get(), 0);
+  }
+
+
+  /// Check that repeats of held keys not part of the sequence being
+  /// entered are still reported.
+  #[test]
+  fn pending_other_repeats() {
+    let now = Instant::now();
+    let mut sequences = Sequences::new(10 * SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["C-x", "C-s"], 1, false);
+
+    let () = sequences.on_key_press(now, "a");
+    assert_eq!(collect(&mut sequences, now), vec!["a"]);
+
+    let () = sequences.on_key_press(now + 4 * SECOND, "C-x");
+    assert_eq!(collect(&mut sequences, now + 6 * SECOND), vec!["a", "a"]);
+    assert_eq!(sequences.pending(), &["C-x"]);
+
+    // Repeats of the pending key itself are still swallowed.
+    assert_eq!(
+      collect(&mut sequences, now + 9 * SECOND),
+      vec!["a", "a", "a"]
+    );
+  }
+
+
+  /// Check that repeats of the key completing a sequence stay
+  /// swallowed until it is released, even if other keys are pressed in
+  /// the meantime.
+  #[test]
+  fn completed_held() {
+    let now = Instant::now();
+    let mut sequences = Sequences::new(SECOND, Config::new(5 * SECOND, SECOND));
+    let () = sequences.bind(vec!["C-x", "C-s"], 1, false);
+
+    let () = sequences.on_key_press(now, "C-x");
+    let () = sequences.on_key_release(now, "C-x");
+    let () = sequences.on_key_press(now, "C-s");
+    assert_eq!(collect(&mut sequences, now), vec!["#1"]);
+
+    let () = sequences.on_key_press(now + SECOND, "a");
+    let () = sequences.on_key_release(now + SECOND, "a");
+    assert_eq!(collect(&mut sequences, now + 7 * SECOND), vec!["a"]);
+
+    // Once released, the key is no longer treated specially.
+    let () = sequences.on_key_release(now + 7 * SECOND, "C-s");
+    assert_eq!(
+      collect(&mut sequences, now + 8 * SECOND),
+      Vec::<String>::new()
+    );
+    let () = sequences.on_key_press(now + 10 * SECOND, "C-s");
+    assert_eq!(
+      collect(&mut sequences, now + 15 * SECOND),
+      vec!["C-s", "C-s"]
+    );
+  }
+}
@@ -0,0 +1,91 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Functionality for persisting the state of [`Keys`][crate::Keys].
+
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::keys::KeyControl;
+use crate::keys::KeyMap;
+use crate::keys::KeyState;
+use crate::Config;
+
+
+/// The persisted state of a single pressed key.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub(crate) struct KeyRecord<K, D> {
+  /// The key in question.
+  pub key: K,
+  /// The key's repetition state, relative to the epoch.
+  pub state: KeyState<Duration>,
+  /// The devices holding the key.
+  pub devices: Vec<D>,
+  /// The instant at which the key got force-released because it was
+  /// considered stuck, if any, relative to the epoch.
+  pub stuck_at: Option<Duration>,
+  /// State influenced by the handler.
+  pub control: KeyControl,
+}
+
+
+/// The persisted state of a [`Keys`][crate::Keys] object.
+///
+/// All instants are expressed relative to an epoch provided when
+/// saving the state via [`Keys::save`][crate::Keys::save] and when
+/// restoring it via [`Keys::restore`][crate::Keys::restore]. That
+/// makes it possible to restore state in a different process, where
+/// instants saved earlier may be meaningless.
+///
+/// With the `serde` feature enabled, this type can be serialized and
+/// deserialized.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct State<K, D = ()> {
+  /// The configuration in use.
+  pub(crate) config: Config,
+  /// The keys currently pressed.
+  pub(crate) keys: Vec<KeyRecord<K, D>>,
+  /// The latest instant observed, relative to the epoch.
+  pub(crate) last_time: Option<Duration>,
+  /// The instant of the last "tick", relative to the epoch.
+  pub(crate) last_tick: Option<Duration>,
+  /// Whether the platform has been observed to generate repeats.
+  pub(crate) platform_repeats: bool,
+  /// The depth of the input context stack.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub(crate) context: usize,
+}
+
+impl<K, D> State<K, D> {
+  /// Retrieve the configuration stored as part of this state.
+  #[inline]
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+}
+
+
+/// A snapshot of the state of a [`Keys`][crate::Keys] object, as
+/// created by [`Keys::snapshot`][crate::Keys::snapshot].
+///
+/// Compared to [`State`], a snapshot is an in-memory copy that is cheap
+/// to create and to [rewind][crate::Keys::rewind] to.
+#[derive(Clone, Debug)]
+pub struct Snapshot<K, I, D = ()> {
+  /// The keys currently pressed.
+  pub(crate) pressed: KeyMap<K, I, D>,
+  /// The latest instant observed so far.
+  pub(crate) last_time: Option<I>,
+  /// The instant of the last "tick".
+  pub(crate) last_tick: Option<I>,
+  /// Whether the platform has been observed to generate repeats.
+  pub(crate) platform_repeats: bool,
+  /// The depth of the input context stack.
+  pub(crate) context: usize,
+}
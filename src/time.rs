@@ -0,0 +1,157 @@
+// Copyright (C) 2025 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+//! Abstractions over the notion of time used by [`Keys`][crate::Keys].
+
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Sub;
+use std::time::Duration;
+
+
+/// A trait for types usable as "instants", i.e., points in time.
+///
+/// This trait is implemented for all types that can be ordered and that
+/// support the necessary arithmetic with [`Duration`]s, most notably
+/// [`std::time::Instant`] and [`FrameInstant`].
+pub trait Timestamp:
+  Copy + Ord + Add<Duration, Output = Self> + AddAssign<Duration> + Sub<Output = Duration>
+{
+}
+
+impl<T> Timestamp for T where
+  T: Copy + Ord + Add<Duration, Output = T> + AddAssign<Duration> + Sub<Output = Duration>
+{
+}
+
+
+/// An instant expressed as an integer frame count, for usage in
+/// fixed-timestep game loops and similar.
+///
+/// Each frame corresponds to a fixed [`Duration`] of
+/// [`FRAME`][FrameInstant::FRAME], derived from the frame rate `FPS`.
+/// Durations added to a [`FrameInstant`] are rounded to the nearest
+/// frame. To have repeats land exactly on frames, timeout and interval
+/// should be expressed as a whole number of frames, e.g., by using
+/// [`FrameInstant::frames`]:
+/// ```
+/// # use keypeat::FrameInstant;
+/// # use keypeat::Keys;
+/// type Frame = FrameInstant<60>;
+/// let mut keys = Keys::<char, Frame>::new(Frame::frames(30), Frame::frames(5));
+/// let () = keys.on_key_press(Frame::new(100), 'a');
+/// let (_, next) = keys.tick(Frame::new(100), |_key, _repeat| true);
+/// assert_eq!(next, Some(Frame::new(130)));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FrameInstant<const FPS: u32 = 60>(u64);
+
+impl<const FPS: u32> FrameInstant<FPS> {
+  /// The duration of a single frame, in nanoseconds.
+  const NANOS: u64 = 1_000_000_000 / FPS as u64;
+
+  /// The duration of a single frame.
+  pub const FRAME: Duration = Duration::from_nanos(Self::NANOS);
+
+  /// Create a [`FrameInstant`] referring to the given frame.
+  #[inline]
+  pub const fn new(frame: u64) -> Self {
+    Self(frame)
+  }
+
+  /// Retrieve the frame this instant refers to.
+  #[inline]
+  pub const fn frame(self) -> u64 {
+    self.0
+  }
+
+  /// Retrieve the [`Duration`] spanning `count` frames.
+  #[inline]
+  pub const fn frames(count: u64) -> Duration {
+    Duration::from_nanos(count.saturating_mul(Self::NANOS))
+  }
+
+  /// Convert `duration` into the nearest number of frames.
+  fn to_frames(duration: Duration) -> u64 {
+    let frame = u128::from(Self::NANOS);
+    let frames = (duration.as_nanos() + frame / 2) / frame;
+    u64::try_from(frames).unwrap_or(u64::MAX)
+  }
+}
+
+impl<const FPS: u32> Add<Duration> for FrameInstant<FPS> {
+  type Output = Self;
+
+  fn add(self, rhs: Duration) -> Self::Output {
+    Self(self.0.saturating_add(Self::to_frames(rhs)))
+  }
+}
+
+impl<const FPS: u32> AddAssign<Duration> for FrameInstant<FPS> {
+  fn add_assign(&mut self, rhs: Duration) {
+    *self = *self + rhs
+  }
+}
+
+impl<const FPS: u32> Sub for FrameInstant<FPS> {
+  type Output = Duration;
+
+  /// Calculate the duration between two instants.
+  ///
+  /// Similar to [`std::time::Instant`], the result saturates at zero if
+  /// `rhs` is later than `self`.
+  fn sub(self, rhs: Self) -> Self::Output {
+    Self::frames(self.0.saturating_sub(rhs.0))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::KeyRepeat;
+  use crate::Keys;
+
+  type Frame = FrameInstant<60>;
+
+
+  /// Check that [`FrameInstant`] arithmetic works as expected.
+  #[test]
+  fn frame_arithmetic() {
+    assert_eq!(Frame::FRAME, Duration::from_nanos(16_666_666));
+    assert_eq!(Frame::new(10) + Frame::frames(5), Frame::new(15));
+    assert_eq!(Frame::new(15) - Frame::new(10), Frame::frames(5));
+    assert_eq!(Frame::new(10) - Frame::new(15), Duration::ZERO);
+    // Durations are rounded to the nearest frame.
+    assert_eq!(Frame::new(0) + Duration::from_millis(660), Frame::new(40));
+    assert_eq!(Frame::new(0) + Duration::from_millis(25), Frame::new(2));
+  }
+
+
+  /// Check that we can drive a [`Keys`] object with frame counts.
+  #[test]
+  fn frame_keys() {
+    let mut keys = Keys::<char, Frame>::new(Frame::frames(30), Frame::frames(7));
+    let mut fired = Vec::new();
+
+    let () = keys.on_key_press(Frame::new(3), 'a');
+    for frame in 3..=60 {
+      let _result = keys.tick(Frame::new(frame), |_key: &char, _repeat: &mut KeyRepeat| {
+        let () = fired.push(frame);
+        true
+      });
+    }
+    assert_eq!(fired, vec![3, 33, 40, 47, 54]);
+
+    // The release should account for all repeats due up to and
+    // including the release frame (61, 68, and 75).
+    let () = keys.on_key_release(Frame::new(75), 'a');
+    let (_changed, next) = keys.tick(Frame::new(80), |_key: &char, _repeat: &mut KeyRepeat| {
+      let () = fired.push(80);
+      true
+    });
+    assert_eq!(fired.len(), 8);
+    assert_eq!(next, None);
+  }
+}